@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::profile::Profile;
+use crate::types::{BasicControllerState, CommonAnalog};
+
+// shapes the travel between the deadzones, 0..1 in and 0..1 out
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Curve {
+    Linear,
+    // above 1 gives finer control near center, below 1 near the edge
+    Power(f32),
+    // smooth monotone curve through [in, out] points, ascending in `in`
+    Spline(Vec<[f32; 2]>),
+}
+
+impl Curve {
+    pub fn apply(&self, t: f32) -> f32 {
+        match self {
+            Curve::Linear => t,
+            Curve::Power(exponent) => t.powf(*exponent),
+            Curve::Spline(points) => spline(points, t),
+        }
+    }
+}
+
+// monotone cubic hermite interpolation (fritsch-carlson), so the curve
+// never overshoots between points and the output keeps rising with the input
+fn spline(points: &[[f32; 2]], t: f32) -> f32 {
+    let last = points.len() - 1;
+    if t <= points[0][0] {
+        return points[0][1];
+    }
+    if t >= points[last][0] {
+        return points[last][1];
+    }
+
+    let slopes: Vec<f32> = points
+        .windows(2)
+        .map(|w| (w[1][1] - w[0][1]) / (w[1][0] - w[0][0]))
+        .collect();
+    let mut tangents = vec![0.0; points.len()];
+    tangents[0] = slopes[0];
+    tangents[last] = slopes[last - 1];
+    for i in 1..last {
+        if slopes[i - 1] * slopes[i] > 0.0 {
+            tangents[i] = (slopes[i - 1] + slopes[i]) / 2.0;
+        }
+    }
+    for (i, slope) in slopes.iter().enumerate() {
+        if *slope == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+        let a = tangents[i] / slope;
+        let b = tangents[i + 1] / slope;
+        let length = a.hypot(b);
+        if length > 3.0 {
+            tangents[i] = 3.0 * a / length * slope;
+            tangents[i + 1] = 3.0 * b / length * slope;
+        }
+    }
+
+    let i = points
+        .windows(2)
+        .position(|w| t < w[1][0])
+        .unwrap_or(last - 1);
+    let [x0, y0] = points[i];
+    let [x1, y1] = points[i + 1];
+    let h = x1 - x0;
+    let s = (t - x0) / h;
+    let s2 = s * s;
+    let s3 = s2 * s;
+    (2.0 * s3 - 3.0 * s2 + 1.0) * y0
+        + (s3 - 2.0 * s2 + s) * h * tangents[i]
+        + (-2.0 * s3 + 3.0 * s2) * y1
+        + (s3 - s2) * h * tangents[i + 1]
+}
+
+// how one analog's raw value becomes the value bindings and the overlay see
+#[derive(Clone, Debug, PartialEq)]
+pub struct AxisSettings {
+    // travel ignored around center
+    pub deadzone: f32,
+    // travel past which the axis reads as fully pushed
+    pub outer: f32,
+    // smallest output once past the deadzone, for games with their own deadzone
+    pub anti_deadzone: f32,
+    pub curve: Curve,
+    pub invert: bool,
+    // for stick axes, measure the deadzones on the stick's distance from center
+    // instead of on this axis alone
+    pub radial: bool,
+}
+
+impl AxisSettings {
+    // maps a distance from center, 0..1, through the deadzones and the curve
+    fn shape(&self, magnitude: f32) -> f32 {
+        if magnitude <= self.deadzone {
+            return 0.0;
+        }
+        let t = ((magnitude - self.deadzone) / (self.outer - self.deadzone)).clamp(0.0, 1.0);
+        self.anti_deadzone + (1.0 - self.anti_deadzone) * self.curve.apply(t)
+    }
+}
+
+// the other half of a stick, for radial deadzones
+fn partner(analog: CommonAnalog) -> Option<CommonAnalog> {
+    match analog {
+        CommonAnalog::LeftStickX => Some(CommonAnalog::LeftStickY),
+        CommonAnalog::LeftStickY => Some(CommonAnalog::LeftStickX),
+        CommonAnalog::RightStickX => Some(CommonAnalog::RightStickY),
+        CommonAnalog::RightStickY => Some(CommonAnalog::RightStickX),
+        _ => None,
+    }
+}
+
+// applies the profile's per-analog settings to raw controller state,
+// before it reaches bindings and the overlay
+pub struct AnalogStage {
+    axes: HashMap<CommonAnalog, AxisSettings>,
+}
+
+impl AnalogStage {
+    pub fn new(profile: &Profile) -> Self {
+        Self {
+            axes: profile.axes.clone(),
+        }
+    }
+
+    pub fn process(&self, raw: &BasicControllerState) -> BasicControllerState {
+        let mut state = *raw;
+        for analog in state.analogs.iter_mut() {
+            let Some(settings) = self.axes.get(&analog.ty) else {
+                continue;
+            };
+            let trigger = matches!(
+                analog.ty,
+                CommonAnalog::LeftLever | CommonAnalog::RightLever
+            );
+            // flipped before the deadzones, so a trigger that rests at 1 reads 0 at rest
+            let value = match (settings.invert, trigger) {
+                (false, _) => analog.value,
+                (true, true) => 1.0 - analog.value,
+                (true, false) => -analog.value,
+            };
+            let shaped = match partner(analog.ty).filter(|_| settings.radial) {
+                Some(other) => {
+                    let other = raw.analog_state_by_type(other).value;
+                    let magnitude = value.hypot(other);
+                    if magnitude > 0.0 {
+                        value / magnitude * settings.shape(magnitude.min(1.0))
+                    } else {
+                        0.0
+                    }
+                }
+                None => value.signum() * settings.shape(value.abs().min(1.0)),
+            };
+            analog.value = shaped;
+        }
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn axis() -> AxisSettings {
+        AxisSettings {
+            deadzone: 0.0,
+            outer: 1.0,
+            anti_deadzone: 0.0,
+            curve: Curve::Linear,
+            invert: false,
+            radial: false,
+        }
+    }
+
+    // raw values through the stage, rounded to keep float noise out of the asserts
+    fn process(
+        axes: &[(CommonAnalog, AxisSettings)],
+        raw: &[(CommonAnalog, f32)],
+    ) -> HashMap<CommonAnalog, f32> {
+        let stage = AnalogStage {
+            axes: axes.iter().cloned().collect(),
+        };
+        let mut state = BasicControllerState::default();
+        for (analog, value) in raw {
+            state.try_update_analog(*analog, *value);
+        }
+        let state = stage.process(&state);
+        raw.iter()
+            .map(|(analog, _)| {
+                let value = state.analog_state_by_type(*analog).value;
+                (*analog, (value * 1000.0).round() / 1000.0)
+            })
+            .collect()
+    }
+
+    fn one(settings: AxisSettings, analog: CommonAnalog, raw: f32) -> f32 {
+        process(&[(analog, settings)], &[(analog, raw)])[&analog]
+    }
+
+    #[test]
+    fn deadzones_scale_the_travel_in_between() {
+        let settings = AxisSettings {
+            deadzone: 0.1,
+            outer: 0.9,
+            anti_deadzone: 0.2,
+            ..axis()
+        };
+        let x = CommonAnalog::LeftStickX;
+        let read = |raw| one(settings.clone(), x, raw);
+        assert_eq!(read(0.05), 0.0);
+        assert_eq!(read(0.1), 0.0);
+        // just past the deadzone starts at the anti-deadzone
+        assert_eq!(read(0.101), 0.201);
+        assert_eq!(read(0.5), 0.6);
+        assert_eq!(read(0.9), 1.0);
+        assert_eq!(read(0.95), 1.0);
+        assert_eq!(read(-0.5), -0.6);
+        assert_eq!(read(-0.05), 0.0);
+    }
+
+    #[test]
+    fn analogs_without_settings_pass_through() {
+        let settings = AxisSettings {
+            deadzone: 0.5,
+            ..axis()
+        };
+        let read = process(
+            &[(CommonAnalog::LeftStickX, settings)],
+            &[
+                (CommonAnalog::LeftStickX, 0.3),
+                (CommonAnalog::LeftStickY, 0.3),
+            ],
+        );
+        assert_eq!(read[&CommonAnalog::LeftStickX], 0.0);
+        assert_eq!(read[&CommonAnalog::LeftStickY], 0.3);
+    }
+
+    #[test]
+    fn an_inverted_trigger_rests_at_zero() {
+        let settings = AxisSettings {
+            deadzone: 0.1,
+            invert: true,
+            ..axis()
+        };
+        let lever = CommonAnalog::RightLever;
+        let read = |raw| one(settings.clone(), lever, raw);
+        assert_eq!(read(1.0), 0.0);
+        // the deadzone is measured from where it rests
+        assert_eq!(read(0.95), 0.0);
+        assert_eq!(read(0.55), 0.389);
+        assert_eq!(read(0.0), 1.0);
+    }
+
+    #[test]
+    fn an_inverted_stick_flips_its_direction() {
+        let settings = AxisSettings {
+            deadzone: 0.1,
+            invert: true,
+            ..axis()
+        };
+        let y = CommonAnalog::LeftStickY;
+        assert_eq!(one(settings.clone(), y, 0.55), -0.5);
+        assert_eq!(one(settings.clone(), y, -1.0), 1.0);
+        assert_eq!(one(settings, y, 0.05), 0.0);
+    }
+
+    #[test]
+    fn a_radial_deadzone_measures_the_whole_stick() {
+        let (x, y) = (CommonAnalog::RightStickX, CommonAnalog::RightStickY);
+        let settings = |radial| AxisSettings {
+            deadzone: 0.2,
+            radial,
+            ..axis()
+        };
+        let axial = [(x, settings(false)), (y, settings(false))];
+        let radial = [(x, settings(true)), (y, settings(true))];
+
+        // each axis is inside the deadzone, the stick is not
+        let read = process(&axial, &[(x, 0.15), (y, 0.15)]);
+        assert_eq!((read[&x], read[&y]), (0.0, 0.0));
+        let read = process(&radial, &[(x, 0.15), (y, 0.15)]);
+        assert_eq!((read[&x], read[&y]), (0.011, 0.011));
+
+        // a radial deadzone keeps the stick's direction
+        let read = process(&axial, &[(x, 0.6), (y, 0.8)]);
+        assert_eq!((read[&x], read[&y]), (0.5, 0.75));
+        let read = process(&radial, &[(x, 0.6), (y, 0.8)]);
+        assert_eq!((read[&x], read[&y]), (0.6, 0.8));
+        let read = process(&radial, &[(x, -0.3), (y, 0.4)]);
+        assert_eq!((read[&x], read[&y]), (-0.225, 0.3));
+    }
+
+    #[test]
+    fn curves_shape_the_travel() {
+        assert_eq!(Curve::Linear.apply(0.3), 0.3);
+        assert_eq!(Curve::Power(2.0).apply(0.5), 0.25);
+        let settings = AxisSettings {
+            deadzone: 0.2,
+            curve: Curve::Power(2.0),
+            ..axis()
+        };
+        assert_eq!(one(settings, CommonAnalog::LeftStickX, 0.6), 0.25);
+    }
+
+    #[test]
+    fn splines_pass_through_their_points() {
+        let points = vec![[0.0, 0.0], [0.2, 0.6], [0.5, 0.65], [1.0, 1.0]];
+        let curve = Curve::Spline(points.clone());
+        for [x, y] in points {
+            assert_eq!(curve.apply(x), y);
+        }
+        assert_eq!(curve.apply(-0.5), 0.0);
+        assert_eq!(curve.apply(1.5), 1.0);
+    }
+
+    #[test]
+    fn splines_never_overshoot_or_fall() {
+        let curves = [
+            vec![[0.0, 0.0], [0.2, 0.6], [0.5, 0.65], [1.0, 1.0]],
+            vec![[0.0, 0.0], [0.1, 0.9], [0.9, 0.95], [1.0, 1.0]],
+            vec![[0.0, 0.0], [0.3, 0.5], [0.6, 0.5], [1.0, 1.0]],
+        ];
+        for points in curves {
+            let curve = Curve::Spline(points.clone());
+            let mut last = 0.0;
+            for step in 0..=1000 {
+                let t = step as f32 / 1000.0;
+                let value = curve.apply(t);
+                assert!(value >= last, "{:?} falls at {}", points, t);
+                assert!(value <= 1.0, "{:?} overshoots at {}", points, t);
+                last = value;
+            }
+        }
+        // a flat stretch stays flat
+        let curve = Curve::Spline(vec![[0.0, 0.0], [0.3, 0.5], [0.6, 0.5], [1.0, 1.0]]);
+        assert_eq!(curve.apply(0.45), 0.5);
+    }
+}
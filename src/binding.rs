@@ -0,0 +1,977 @@
+use std::time::{Duration, Instant};
+
+use evdev::{EventType, InputEvent, Key, RelativeAxisType};
+use serde::Deserialize;
+
+use crate::frames::FrameDiff;
+use crate::output::OutputSink;
+use crate::profile::{Direction, Pointer, Profile, Radial, Scroll, Threshold};
+use crate::radial::RadialSource;
+use crate::types::{BasicControllerState, CommonAnalog, CommonButton};
+
+// button values above this count as pressed
+pub const BUTTON_PRESS: f32 = 0.5;
+
+pub const DEFAULT_HOLD_MS: u64 = 200;
+
+fn default_hold_ms() -> u64 {
+    DEFAULT_HOLD_MS
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    // held for as long as the input is held
+    Key(Key),
+    // pressed in order, released in reverse
+    Chord(Vec<Key>),
+    // typed once on press
+    Text(String),
+    // BTN_LEFT, BTN_RIGHT, ...
+    MouseButton(Key),
+    // moved once on press
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    // scrolled once on press, in wheel notches
+    Scroll {
+        x: i32,
+        y: i32,
+    },
+    // swallows the input
+    Consume,
+    // the named layer is active while held
+    Layer(String),
+    // the named layer is switched on or off on press
+    ToggleLayer(String),
+    // `tap` if released within the timeout, otherwise `hold` for as long as it is held.
+    // pressing another button before the timeout also makes it a hold.
+    TapHold {
+        tap: Box<Action>,
+        hold: Box<Action>,
+        #[serde(default = "default_hold_ms")]
+        timeout_ms: u64,
+    },
+}
+
+impl Action {
+    // keys (and mouse buttons) the action may press
+    pub fn keys(&self) -> Vec<Key> {
+        match self {
+            Action::Key(key) | Action::MouseButton(key) => vec![*key],
+            Action::Chord(keys) => keys.clone(),
+            Action::Text(text) => {
+                let mut keys = Vec::new();
+                for (key, shift) in text.chars().filter_map(char_to_key) {
+                    keys.push(key);
+                    if shift {
+                        keys.push(Key::KEY_LEFTSHIFT);
+                    }
+                }
+                keys
+            }
+            Action::MouseMove { .. }
+            | Action::Scroll { .. }
+            | Action::Consume
+            | Action::Layer(_)
+            | Action::ToggleLayer(_) => vec![],
+            Action::TapHold { tap, hold, .. } => {
+                let mut keys = tap.keys();
+                keys.extend(hold.keys());
+                keys
+            }
+        }
+    }
+
+    pub fn press(&self, events: &mut Vec<InputEvent>) {
+        match self {
+            Action::Key(key) | Action::MouseButton(key) => events.push(key_event(*key, 1)),
+            Action::Chord(keys) => events.extend(keys.iter().map(|k| key_event(*k, 1))),
+            Action::Text(text) => {
+                for (key, shift) in text.chars().filter_map(char_to_key) {
+                    if shift {
+                        events.push(key_event(Key::KEY_LEFTSHIFT, 1));
+                    }
+                    events.push(key_event(key, 1));
+                    events.push(key_event(key, 0));
+                    if shift {
+                        events.push(key_event(Key::KEY_LEFTSHIFT, 0));
+                    }
+                }
+            }
+            Action::MouseMove { x, y } => {
+                if *x != 0 {
+                    events.push(rel_event(RelativeAxisType::REL_X, *x));
+                }
+                if *y != 0 {
+                    events.push(rel_event(RelativeAxisType::REL_Y, *y));
+                }
+            }
+            Action::Scroll { x, y } => {
+                if *y != 0 {
+                    events.push(rel_event(RelativeAxisType::REL_WHEEL, *y));
+                }
+                if *x != 0 {
+                    events.push(rel_event(RelativeAxisType::REL_HWHEEL, *x));
+                }
+            }
+            // layers only mean something to the engine
+            Action::Consume | Action::Layer(_) | Action::ToggleLayer(_) => (),
+            // outside of the engine there is no timing, so it behaves as its tap
+            Action::TapHold { tap, .. } => tap.press(events),
+        }
+    }
+
+    pub fn release(&self, events: &mut Vec<InputEvent>) {
+        match self {
+            Action::Key(key) | Action::MouseButton(key) => events.push(key_event(*key, 0)),
+            Action::Chord(keys) => events.extend(keys.iter().rev().map(|k| key_event(*k, 0))),
+            Action::Text(_)
+            | Action::MouseMove { .. }
+            | Action::Scroll { .. }
+            | Action::Consume
+            | Action::Layer(_)
+            | Action::ToggleLayer(_) => (),
+            Action::TapHold { tap, .. } => tap.release(events),
+        }
+    }
+}
+
+pub fn key_event(key: Key, value: i32) -> InputEvent {
+    InputEvent::new(EventType::KEY, key.code(), value)
+}
+
+pub fn rel_event(axis: RelativeAxisType, value: i32) -> InputEvent {
+    InputEvent::new(EventType::RELATIVE, axis.0, value)
+}
+
+pub fn is_mouse_button(key: Key) -> bool {
+    (Key::BTN_LEFT.code()..=Key::BTN_TASK.code()).contains(&key.code())
+}
+
+// us layout. the bool is whether shift is needed.
+pub fn char_to_key(ch: char) -> Option<(Key, bool)> {
+    let lower = ch.to_ascii_lowercase();
+    let letter = match lower {
+        'a' => Some(Key::KEY_A),
+        'b' => Some(Key::KEY_B),
+        'c' => Some(Key::KEY_C),
+        'd' => Some(Key::KEY_D),
+        'e' => Some(Key::KEY_E),
+        'f' => Some(Key::KEY_F),
+        'g' => Some(Key::KEY_G),
+        'h' => Some(Key::KEY_H),
+        'i' => Some(Key::KEY_I),
+        'j' => Some(Key::KEY_J),
+        'k' => Some(Key::KEY_K),
+        'l' => Some(Key::KEY_L),
+        'm' => Some(Key::KEY_M),
+        'n' => Some(Key::KEY_N),
+        'o' => Some(Key::KEY_O),
+        'p' => Some(Key::KEY_P),
+        'q' => Some(Key::KEY_Q),
+        'r' => Some(Key::KEY_R),
+        's' => Some(Key::KEY_S),
+        't' => Some(Key::KEY_T),
+        'u' => Some(Key::KEY_U),
+        'v' => Some(Key::KEY_V),
+        'w' => Some(Key::KEY_W),
+        'x' => Some(Key::KEY_X),
+        'y' => Some(Key::KEY_Y),
+        'z' => Some(Key::KEY_Z),
+        _ => None,
+    };
+    if let Some(key) = letter {
+        return Some((key, ch.is_ascii_uppercase()));
+    }
+    let key = match ch {
+        '1' => (Key::KEY_1, false),
+        '2' => (Key::KEY_2, false),
+        '3' => (Key::KEY_3, false),
+        '4' => (Key::KEY_4, false),
+        '5' => (Key::KEY_5, false),
+        '6' => (Key::KEY_6, false),
+        '7' => (Key::KEY_7, false),
+        '8' => (Key::KEY_8, false),
+        '9' => (Key::KEY_9, false),
+        '0' => (Key::KEY_0, false),
+        '!' => (Key::KEY_1, true),
+        '@' => (Key::KEY_2, true),
+        '#' => (Key::KEY_3, true),
+        '$' => (Key::KEY_4, true),
+        '%' => (Key::KEY_5, true),
+        '^' => (Key::KEY_6, true),
+        '&' => (Key::KEY_7, true),
+        '*' => (Key::KEY_8, true),
+        '(' => (Key::KEY_9, true),
+        ')' => (Key::KEY_0, true),
+        ' ' => (Key::KEY_SPACE, false),
+        '\n' => (Key::KEY_ENTER, false),
+        '\t' => (Key::KEY_TAB, false),
+        '-' => (Key::KEY_MINUS, false),
+        '_' => (Key::KEY_MINUS, true),
+        '=' => (Key::KEY_EQUAL, false),
+        '+' => (Key::KEY_EQUAL, true),
+        '[' => (Key::KEY_LEFTBRACE, false),
+        '{' => (Key::KEY_LEFTBRACE, true),
+        ']' => (Key::KEY_RIGHTBRACE, false),
+        '}' => (Key::KEY_RIGHTBRACE, true),
+        '\\' => (Key::KEY_BACKSLASH, false),
+        '|' => (Key::KEY_BACKSLASH, true),
+        ';' => (Key::KEY_SEMICOLON, false),
+        ':' => (Key::KEY_SEMICOLON, true),
+        '\'' => (Key::KEY_APOSTROPHE, false),
+        '"' => (Key::KEY_APOSTROPHE, true),
+        '`' => (Key::KEY_GRAVE, false),
+        '~' => (Key::KEY_GRAVE, true),
+        ',' => (Key::KEY_COMMA, false),
+        '<' => (Key::KEY_COMMA, true),
+        '.' => (Key::KEY_DOT, false),
+        '>' => (Key::KEY_DOT, true),
+        '/' => (Key::KEY_SLASH, false),
+        '?' => (Key::KEY_SLASH, true),
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Input {
+    Button(CommonButton),
+    Analog(CommonAnalog, Direction),
+    // second stage of a two-stage analog
+    FullPull(CommonAnalog, Direction),
+}
+
+impl Input {
+    fn value(&self, state: &BasicControllerState) -> f32 {
+        match *self {
+            Input::Button(button) => state.button_state_by_type(button).value,
+            Input::Analog(analog, direction) | Input::FullPull(analog, direction) => {
+                let value = state.analog_state_by_type(analog).value;
+                match direction {
+                    Direction::Positive => value,
+                    Direction::Negative => -value,
+                }
+            }
+        }
+    }
+}
+
+const BUTTON_THRESHOLD: Threshold = Threshold {
+    press: BUTTON_PRESS,
+    release: BUTTON_PRESS,
+};
+
+#[derive(Copy, Clone, PartialEq)]
+enum Phase {
+    Released,
+    Pressed,
+    // tap/hold that has not decided yet
+    Pending(Instant),
+    // tap/hold that became a hold, its hold action is down
+    Holding,
+}
+
+// one per input bound on any layer. the action is looked up on press
+// and kept until release, so layer changes in between can't strand a key.
+// the threshold follows the active layers while released and is kept while pressed.
+struct Slot {
+    input: Input,
+    phase: Phase,
+    threshold: Threshold,
+    action: Option<Action>,
+}
+
+struct Bind {
+    input: Input,
+    threshold: Threshold,
+    action: Action,
+}
+
+struct Layers {
+    profile_layers: Vec<LayerBinds>,
+    // how many momentary layer buttons hold each layer
+    held: Vec<u32>,
+    toggled: Vec<bool>,
+}
+
+struct LayerBinds {
+    name: String,
+    binds: Vec<Bind>,
+    radials: Vec<Radial>,
+    pointer: Option<Pointer>,
+    scroll: Option<Scroll>,
+}
+
+impl Layers {
+    fn is_active(&self, index: usize) -> bool {
+        index == 0 || self.held[index] > 0 || self.toggled[index]
+    }
+
+    // topmost active layer that binds the input
+    fn lookup(&self, input: Input) -> Option<&Bind> {
+        for (index, layer) in self.profile_layers.iter().enumerate().rev() {
+            if !self.is_active(index) {
+                continue;
+            }
+            if let Some(bind) = layer.binds.iter().find(|b| b.input == input) {
+                return Some(bind);
+            }
+        }
+        None
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.profile_layers.iter().position(|l| l.name == name)
+    }
+
+    // layer actions change the layer state, everything else becomes events
+    fn press(&mut self, action: &Action, events: &mut Vec<InputEvent>) {
+        match action {
+            Action::Layer(name) => {
+                if let Some(index) = self.index_of(name) {
+                    self.held[index] += 1;
+                }
+            }
+            Action::ToggleLayer(name) => {
+                if let Some(index) = self.index_of(name) {
+                    self.toggled[index] = !self.toggled[index];
+                }
+            }
+            action => action.press(events),
+        }
+    }
+
+    fn release(&mut self, action: &Action, events: &mut Vec<InputEvent>) {
+        match action {
+            Action::Layer(name) => {
+                if let Some(index) = self.index_of(name) {
+                    self.held[index] = self.held[index].saturating_sub(1);
+                }
+            }
+            Action::ToggleLayer(_) => (),
+            action => action.release(events),
+        }
+    }
+}
+
+// controller inputs the active layers use, which the virtual gamepad doesn't pass on
+#[derive(Default)]
+pub struct Claims {
+    pub buttons: Vec<CommonButton>,
+    pub analogs: Vec<CommonAnalog>,
+}
+
+impl Claims {
+    fn source(&mut self, source: RadialSource) {
+        self.buttons.extend(source.buttons());
+        self.analogs.extend(source.analogs());
+    }
+
+    fn input(&mut self, input: Input) {
+        match input {
+            Input::Button(button) => self.buttons.push(button),
+            Input::Analog(analog, _) | Input::FullPull(analog, _) => self.analogs.push(analog),
+        }
+    }
+}
+
+// turns controller state transitions into actions.
+// only edges are acted on, so feeding the same state twice sends nothing,
+// except that pending tap/holds are resolved once their timeout passes.
+pub struct BindingEngine {
+    slots: Vec<Slot>,
+    layers: Layers,
+}
+
+impl BindingEngine {
+    pub fn new(profile: &Profile) -> Self {
+        let mut profile_layers = Vec::new();
+        for layer in std::iter::once(&profile.base).chain(profile.layers.iter()) {
+            let mut binds: Vec<Bind> = layer
+                .buttons
+                .iter()
+                .map(|b| Bind {
+                    input: Input::Button(b.button),
+                    threshold: BUTTON_THRESHOLD,
+                    action: b.action.clone(),
+                })
+                .collect();
+            for bind in layer.analogs.iter() {
+                binds.push(Bind {
+                    input: Input::Analog(bind.analog, bind.direction),
+                    threshold: bind.threshold,
+                    action: bind.action.clone(),
+                });
+                if let Some(full) = &bind.full {
+                    binds.push(Bind {
+                        input: Input::FullPull(bind.analog, bind.direction),
+                        threshold: full.threshold,
+                        action: full.action.clone(),
+                    });
+                }
+            }
+            profile_layers.push(LayerBinds {
+                name: layer.name.clone(),
+                binds,
+                radials: layer.radials.clone(),
+                pointer: layer.pointer.clone(),
+                scroll: layer.scroll.clone(),
+            });
+        }
+
+        let mut slots: Vec<Slot> = Vec::new();
+        for layer in profile_layers.iter() {
+            for bind in layer.binds.iter() {
+                if !slots.iter().any(|s| s.input == bind.input) {
+                    slots.push(Slot {
+                        input: bind.input,
+                        phase: Phase::Released,
+                        threshold: bind.threshold,
+                        action: None,
+                    });
+                }
+            }
+        }
+
+        let count = profile_layers.len();
+        Self {
+            slots,
+            layers: Layers {
+                profile_layers,
+                held: vec![0; count],
+                toggled: vec![false; count],
+            },
+        }
+    }
+
+    // radial menu of the topmost active layer that has one for `source`
+    pub fn radial(&self, source: RadialSource) -> Option<&Radial> {
+        self.layers
+            .profile_layers
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, _)| self.layers.is_active(*index))
+            .find_map(|(_, layer)| layer.radials.iter().find(|r| r.source == source))
+    }
+
+    // pointer of the topmost active layer that has one
+    pub fn pointer(&self) -> Option<&Pointer> {
+        self.layers
+            .profile_layers
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, _)| self.layers.is_active(*index))
+            .find_map(|(_, layer)| layer.pointer.as_ref())
+    }
+
+    // scrolling of the topmost active layer that has it
+    pub fn scroll(&self) -> Option<&Scroll> {
+        self.layers
+            .profile_layers
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, _)| self.layers.is_active(*index))
+            .find_map(|(_, layer)| layer.scroll.as_ref())
+    }
+
+    // everything bound on an active layer, plus whatever is still held from
+    // a layer that was switched off
+    pub fn claims(&self) -> Claims {
+        let mut claims = Claims::default();
+        for (index, layer) in self.layers.profile_layers.iter().enumerate() {
+            if self.layers.is_active(index) {
+                for bind in layer.binds.iter() {
+                    claims.input(bind.input);
+                }
+            }
+        }
+        for slot in self.slots.iter().filter(|s| s.phase != Phase::Released) {
+            claims.input(slot.input);
+        }
+        for source in RadialSource::ALL {
+            let Some(radial) = self.radial(source) else {
+                continue;
+            };
+            claims.source(source);
+            claims.buttons.extend(radial.back);
+            if let Some(text_entry) = &radial.text_entry {
+                claims.buttons.push(text_entry.select);
+                claims.buttons.push(text_entry.accept);
+            }
+        }
+        if let Some(pointer) = self.pointer() {
+            claims.source(pointer.source);
+            claims.buttons.extend(pointer.precision);
+        }
+        if let Some(scroll) = self.scroll() {
+            if let Some(source) = scroll.source {
+                claims.source(source);
+            }
+            claims.analogs.extend(scroll.up);
+            claims.analogs.extend(scroll.down);
+        }
+        claims
+    }
+
+    pub fn update(
+        &mut self,
+        state: &BasicControllerState,
+        diff: &FrameDiff,
+        now: Instant,
+        out: &mut dyn OutputSink,
+    ) {
+        let mut events = Vec::new();
+        // a released slot presses at the threshold of the bind it would press now
+        for slot in self.slots.iter_mut() {
+            if slot.phase == Phase::Released {
+                if let Some(bind) = self.layers.lookup(slot.input) {
+                    slot.threshold = bind.threshold;
+                }
+            }
+        }
+        let active: Vec<bool> = self
+            .slots
+            .iter()
+            .map(|slot| {
+                let value = slot.input.value(state);
+                match slot.phase {
+                    Phase::Released => value > slot.threshold.press,
+                    _ => value > slot.threshold.release,
+                }
+            })
+            .collect();
+
+        // permissive hold: pressing anything else while a tap/hold is undecided makes it a hold,
+        // and the hold action goes out before whatever was pressed.
+        let other_pressed = !diff.pressed.is_empty()
+            || self
+                .slots
+                .iter()
+                .zip(active.iter())
+                .any(|(slot, active)| slot.phase == Phase::Released && *active);
+        for (slot, active) in self.slots.iter_mut().zip(active.iter()) {
+            let (
+                Phase::Pending(since),
+                Some(Action::TapHold {
+                    hold, timeout_ms, ..
+                }),
+            ) = (slot.phase, &slot.action)
+            else {
+                continue;
+            };
+            let timed_out = now.duration_since(since) >= Duration::from_millis(*timeout_ms);
+            if timed_out || (other_pressed && *active) {
+                self.layers.press(hold, &mut events);
+                slot.phase = Phase::Holding;
+            }
+        }
+
+        // a text entry menu's select and accept buttons only pick completions
+        let completion_buttons: Vec<Input> = RadialSource::ALL
+            .iter()
+            .filter_map(|source| self.radial(*source)?.text_entry.as_ref())
+            .flat_map(|text_entry| [text_entry.select, text_entry.accept])
+            .map(Input::Button)
+            .collect();
+        for (slot, active) in self.slots.iter_mut().zip(active) {
+            match (slot.phase, active) {
+                (Phase::Released, true) => {
+                    slot.action = match completion_buttons.contains(&slot.input) {
+                        true => Some(Action::Consume),
+                        false => self.layers.lookup(slot.input).map(|b| b.action.clone()),
+                    };
+                    match &slot.action {
+                        Some(Action::TapHold { .. }) => slot.phase = Phase::Pending(now),
+                        Some(action) => {
+                            self.layers.press(action, &mut events);
+                            slot.phase = Phase::Pressed;
+                        }
+                        // unbound on every active layer, wait for the release
+                        None => slot.phase = Phase::Pressed,
+                    }
+                }
+                (Phase::Pressed, false) => {
+                    if let Some(action) = slot.action.take() {
+                        self.layers.release(&action, &mut events);
+                    }
+                    slot.phase = Phase::Released;
+                }
+                (Phase::Pending(_), false) => {
+                    if let Some(Action::TapHold { tap, .. }) = slot.action.take() {
+                        self.layers.press(&tap, &mut events);
+                        self.layers.release(&tap, &mut events);
+                    }
+                    slot.phase = Phase::Released;
+                }
+                (Phase::Holding, false) => {
+                    if let Some(Action::TapHold { hold, .. }) = slot.action.take() {
+                        self.layers.release(&hold, &mut events);
+                    }
+                    slot.phase = Phase::Released;
+                }
+                _ => (),
+            }
+        }
+
+        if !events.is_empty() {
+            out.send(&events);
+        }
+    }
+
+    // releases whatever is currently held, e.g. before the engine is replaced.
+    // undecided tap/holds are dropped without sending anything.
+    pub fn release_all(&mut self, out: &mut dyn OutputSink) {
+        let mut events = Vec::new();
+        for slot in self.slots.iter_mut() {
+            match (slot.phase, slot.action.take()) {
+                (Phase::Pressed, Some(action)) => self.layers.release(&action, &mut events),
+                (Phase::Holding, Some(Action::TapHold { hold, .. })) => {
+                    self.layers.release(&hold, &mut events)
+                }
+                _ => (),
+            }
+            slot.phase = Phase::Released;
+        }
+        if !events.is_empty() {
+            out.send(&events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use evdev::{InputEventKind, Key};
+
+    use super::*;
+    use crate::frames::Frames;
+    use crate::output::Recorder;
+
+    // an engine fed whole pad states, with what it sent recorded
+    struct Pad {
+        engine: BindingEngine,
+        frames: Frames,
+        state: BasicControllerState,
+        out: Recorder,
+        start: Instant,
+    }
+
+    impl Pad {
+        fn new(src: &str) -> Self {
+            Self {
+                engine: BindingEngine::new(&Profile::parse(src).unwrap()),
+                frames: Frames::default(),
+                state: BasicControllerState::default(),
+                out: Recorder::default(),
+                start: Instant::now(),
+            }
+        }
+
+        // the button moves to `value` and a frame runs `ms` after the start
+        fn set(&mut self, button: CommonButton, value: f32, ms: u64) {
+            self.state.try_update_button(button, value);
+            self.frame(ms);
+        }
+
+        // the analog moves to `value` and a frame runs `ms` after the start
+        fn pull(&mut self, analog: CommonAnalog, value: f32, ms: u64) {
+            self.state.try_update_analog(analog, value);
+            self.frame(ms);
+        }
+
+        // a frame with nothing changed
+        fn frame(&mut self, ms: u64) {
+            let now = self.start + Duration::from_millis(ms);
+            let diff = self.frames.push(self.state, now);
+            self.engine.update(&self.state, &diff, now, &mut self.out);
+        }
+
+        // key events sent since the last call, frame by frame
+        fn sent(&mut self) -> Vec<Vec<(Key, i32)>> {
+            self.out
+                .frames
+                .drain(..)
+                .map(|frame| {
+                    frame
+                        .iter()
+                        .filter_map(|event| match event.kind() {
+                            InputEventKind::Key(key) => Some((key, event.value())),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn dispatches_on_edges_only() {
+        let mut pad = Pad::new("[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_A\" }\n");
+        pad.frame(0);
+        assert!(pad.sent().is_empty());
+        pad.set(CommonButton::FaceSouth, 1.0, 10);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1)]]);
+        // held, or moving without crossing the threshold, sends nothing
+        pad.frame(20);
+        pad.set(CommonButton::FaceSouth, 0.7, 30);
+        assert!(pad.sent().is_empty());
+        pad.set(CommonButton::FaceSouth, 0.0, 40);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 0)]]);
+        pad.frame(50);
+        assert!(pad.sent().is_empty());
+    }
+
+    #[test]
+    fn chord_presses_in_order_and_releases_in_reverse() {
+        let mut pad = Pad::new(
+            "[[bind]]\nbutton = \"FaceSouth\"\naction = { chord = [\"KEY_LEFTCTRL\", \"KEY_LEFTSHIFT\", \"KEY_T\"] }\n",
+        );
+        pad.set(CommonButton::FaceSouth, 1.0, 0);
+        pad.set(CommonButton::FaceSouth, 0.0, 10);
+        assert_eq!(
+            pad.sent(),
+            vec![
+                vec![
+                    (Key::KEY_LEFTCTRL, 1),
+                    (Key::KEY_LEFTSHIFT, 1),
+                    (Key::KEY_T, 1)
+                ],
+                vec![
+                    (Key::KEY_T, 0),
+                    (Key::KEY_LEFTSHIFT, 0),
+                    (Key::KEY_LEFTCTRL, 0)
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn text_is_typed_once_on_press() {
+        let mut pad = Pad::new("[[bind]]\nbutton = \"FaceSouth\"\naction = { text = \"Hi\" }\n");
+        pad.set(CommonButton::FaceSouth, 1.0, 0);
+        assert_eq!(
+            pad.sent(),
+            vec![vec![
+                (Key::KEY_LEFTSHIFT, 1),
+                (Key::KEY_H, 1),
+                (Key::KEY_H, 0),
+                (Key::KEY_LEFTSHIFT, 0),
+                (Key::KEY_I, 1),
+                (Key::KEY_I, 0),
+            ]]
+        );
+        pad.frame(10);
+        pad.set(CommonButton::FaceSouth, 0.0, 20);
+        assert!(pad.sent().is_empty());
+    }
+
+    #[test]
+    fn consume_sends_nothing() {
+        let mut pad = Pad::new(
+            "[[bind]]\nbutton = \"FaceSouth\"\naction = \"consume\"\n\n[[bind]]\nbutton = \"FaceEast\"\naction = { key = \"KEY_B\" }\n",
+        );
+        pad.set(CommonButton::FaceSouth, 1.0, 0);
+        pad.set(CommonButton::FaceSouth, 0.0, 10);
+        assert!(pad.sent().is_empty());
+        // other binds are unaffected
+        pad.set(CommonButton::FaceEast, 1.0, 20);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_B, 1)]]);
+    }
+
+    const TAP_HOLD: &str = "[[bind]]\nbutton = \"FaceEast\"\naction = { tap_hold = { tap = { key = \"KEY_ESC\" }, hold = { key = \"KEY_LEFTALT\" }, timeout_ms = 200 } }\n\n[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_A\" }\n";
+
+    #[test]
+    fn tap_hold_taps_when_released_in_time() {
+        let mut pad = Pad::new(TAP_HOLD);
+        pad.set(CommonButton::FaceEast, 1.0, 0);
+        pad.frame(150);
+        assert!(pad.sent().is_empty());
+        pad.set(CommonButton::FaceEast, 0.0, 199);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_ESC, 1), (Key::KEY_ESC, 0)]]);
+    }
+
+    #[test]
+    fn tap_hold_holds_after_the_timeout() {
+        let mut pad = Pad::new(TAP_HOLD);
+        pad.set(CommonButton::FaceEast, 1.0, 0);
+        pad.frame(199);
+        assert!(pad.sent().is_empty());
+        pad.frame(200);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_LEFTALT, 1)]]);
+        pad.frame(500);
+        pad.set(CommonButton::FaceEast, 0.0, 600);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_LEFTALT, 0)]]);
+    }
+
+    #[test]
+    fn permissive_hold_goes_out_before_the_other_press() {
+        let mut pad = Pad::new(TAP_HOLD);
+        pad.set(CommonButton::FaceEast, 1.0, 0);
+        pad.set(CommonButton::FaceSouth, 1.0, 50);
+        assert_eq!(
+            pad.sent(),
+            vec![vec![(Key::KEY_LEFTALT, 1), (Key::KEY_A, 1)]]
+        );
+        pad.set(CommonButton::FaceEast, 0.0, 80);
+        pad.set(CommonButton::FaceSouth, 0.0, 90);
+        assert_eq!(
+            pad.sent(),
+            vec![vec![(Key::KEY_LEFTALT, 0)], vec![(Key::KEY_A, 0)]]
+        );
+    }
+
+    const LAYERS: &str = "[[bind]]\nbutton = \"LegacyLT\"\naction = { layer = \"nav\" }\n\n[[bind]]\nbutton = \"Select\"\naction = { toggle_layer = \"nav\" }\n\n[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_A\" }\n\n[[bind]]\nbutton = \"FaceEast\"\naction = { key = \"KEY_E\" }\n\n[[layer]]\nname = \"nav\"\n\n[[layer.bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_B\" }\n\n[[layer.bind]]\nbutton = \"FaceEast\"\naction = \"consume\"\n";
+
+    #[test]
+    fn held_layer_wins_and_falls_through() {
+        let mut pad = Pad::new(LAYERS);
+        pad.set(CommonButton::LegacyLT, 1.0, 0);
+        pad.set(CommonButton::FaceSouth, 1.0, 10);
+        pad.set(CommonButton::FaceSouth, 0.0, 20);
+        // consumed on the layer, even though the base binds it
+        pad.set(CommonButton::FaceEast, 1.0, 30);
+        pad.set(CommonButton::FaceEast, 0.0, 40);
+        pad.set(CommonButton::LegacyLT, 0.0, 50);
+        pad.set(CommonButton::FaceSouth, 1.0, 60);
+        assert_eq!(
+            pad.sent(),
+            vec![
+                vec![(Key::KEY_B, 1)],
+                vec![(Key::KEY_B, 0)],
+                vec![(Key::KEY_A, 1)]
+            ]
+        );
+    }
+
+    #[test]
+    fn key_held_across_a_layer_switch_releases_what_it_pressed() {
+        let mut pad = Pad::new(LAYERS);
+        // pressed on the base, released with the layer up
+        pad.set(CommonButton::FaceSouth, 1.0, 0);
+        pad.set(CommonButton::LegacyLT, 1.0, 10);
+        pad.set(CommonButton::FaceSouth, 0.0, 20);
+        assert_eq!(
+            pad.sent(),
+            vec![vec![(Key::KEY_A, 1)], vec![(Key::KEY_A, 0)]]
+        );
+        // pressed on the layer, released after the layer went away
+        pad.set(CommonButton::FaceSouth, 1.0, 30);
+        pad.set(CommonButton::LegacyLT, 0.0, 40);
+        pad.set(CommonButton::FaceSouth, 0.0, 50);
+        assert_eq!(
+            pad.sent(),
+            vec![vec![(Key::KEY_B, 1)], vec![(Key::KEY_B, 0)]]
+        );
+    }
+
+    #[test]
+    fn toggled_layer_stays_until_toggled_again() {
+        let mut pad = Pad::new(LAYERS);
+        pad.set(CommonButton::Select, 1.0, 0);
+        pad.set(CommonButton::Select, 0.0, 10);
+        pad.set(CommonButton::FaceSouth, 1.0, 20);
+        pad.set(CommonButton::FaceSouth, 0.0, 30);
+        pad.set(CommonButton::Select, 1.0, 40);
+        pad.set(CommonButton::Select, 0.0, 50);
+        pad.set(CommonButton::FaceSouth, 1.0, 60);
+        assert_eq!(
+            pad.sent(),
+            vec![
+                vec![(Key::KEY_B, 1)],
+                vec![(Key::KEY_B, 0)],
+                vec![(Key::KEY_A, 1)]
+            ]
+        );
+    }
+
+    #[test]
+    fn analogs_press_past_the_press_threshold_and_release_at_the_release() {
+        let mut pad = Pad::new(
+            "[[analog]]\nanalog = \"RightLever\"\npress = 0.5\nrelease = 0.3\naction = { key = \"KEY_A\" }\n",
+        );
+        let lever = CommonAnalog::RightLever;
+        pad.pull(lever, 0.5, 0);
+        assert!(pad.sent().is_empty());
+        pad.pull(lever, 0.51, 10);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1)]]);
+        // between the thresholds it stays down
+        pad.pull(lever, 0.4, 20);
+        pad.pull(lever, 0.31, 30);
+        assert!(pad.sent().is_empty());
+        pad.pull(lever, 0.3, 40);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 0)]]);
+        // and up again it waits for the press threshold
+        pad.pull(lever, 0.45, 50);
+        assert!(pad.sent().is_empty());
+        pad.pull(lever, 0.6, 60);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1)]]);
+    }
+
+    #[test]
+    fn analog_thresholds_default_to_the_profile_threshold() {
+        let mut pad = Pad::new(
+            "[thresholds]\nanalog = 0.2\n\n[[analog]]\nanalog = \"RightLever\"\naction = { key = \"KEY_A\" }\n",
+        );
+        let lever = CommonAnalog::RightLever;
+        pad.pull(lever, 0.2, 0);
+        assert!(pad.sent().is_empty());
+        pad.pull(lever, 0.21, 10);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1)]]);
+        // without a release threshold it releases where it pressed
+        pad.pull(lever, 0.2, 20);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 0)]]);
+    }
+
+    #[test]
+    fn a_stick_axis_binds_each_direction() {
+        let mut pad = Pad::new(
+            "[[analog]]\nanalog = \"LeftStickY\"\npress = 0.5\nrelease = 0.4\naction = { key = \"KEY_W\" }\n\n\
+             [[analog]]\nanalog = \"LeftStickY\"\ndirection = \"negative\"\npress = 0.5\nrelease = 0.4\naction = { key = \"KEY_S\" }\n",
+        );
+        let y = CommonAnalog::LeftStickY;
+        pad.pull(y, 0.6, 0);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_W, 1)]]);
+        pad.pull(y, -0.45, 10);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_W, 0)]]);
+        pad.pull(y, -0.6, 20);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_S, 1)]]);
+        // straight across center in one frame
+        pad.pull(y, 1.0, 30);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_W, 1), (Key::KEY_S, 0)]]);
+        pad.pull(y, 0.0, 40);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_W, 0)]]);
+    }
+
+    #[test]
+    fn a_full_pull_holds_on_top_of_the_first_stage() {
+        let mut pad = Pad::new(
+            "[[analog]]\nanalog = \"LeftLever\"\npress = 0.2\nrelease = 0.15\naction = { key = \"KEY_A\" }\n\
+             full = { press = 0.9, release = 0.8, action = { key = \"KEY_B\" } }\n",
+        );
+        let lever = CommonAnalog::LeftLever;
+        pad.pull(lever, 0.5, 0);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1)]]);
+        pad.pull(lever, 0.95, 10);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_B, 1)]]);
+        pad.pull(lever, 0.85, 20);
+        assert!(pad.sent().is_empty());
+        pad.pull(lever, 0.8, 30);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_B, 0)]]);
+        pad.pull(lever, 0.85, 40);
+        assert!(pad.sent().is_empty());
+        // both stages in one frame, each way
+        pad.pull(lever, 0.0, 50);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 0)]]);
+        pad.pull(lever, 1.0, 60);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 1), (Key::KEY_B, 1)]]);
+        pad.pull(lever, 0.1, 70);
+        assert_eq!(pad.sent(), vec![vec![(Key::KEY_A, 0), (Key::KEY_B, 0)]]);
+    }
+}
@@ -8,6 +8,8 @@ use femtovg::{Align, Baseline, CompositeOperation, FontId};
 use gtk::glib::subclass::prelude::*;
 use gtk::{glib, glib::Properties, prelude::*, subclass::prelude::*};
 
+use crate::profile::{Rgba, Theme};
+use crate::radial::RadialLayout;
 use crate::RES;
 
 #[derive(Properties)]
@@ -22,6 +24,20 @@ pub struct RadialMenu {
     x: RefCell<f32>,
     #[property(name = "y", set, type = f32)]
     y: RefCell<f32>,
+    #[property(name = "selected", set, type = i32)]
+    selected: RefCell<i32>,
+    #[property(name = "breadcrumbs", set, type = String)]
+    breadcrumbs: RefCell<String>,
+    #[property(name = "completions", set, type = String)]
+    completions: RefCell<String>,
+    #[property(name = "completion", set, type = i32)]
+    completion: RefCell<i32>,
+    #[property(name = "status", set, type = String)]
+    status: RefCell<String>,
+    #[property(name = "toast", set, type = String)]
+    toast: RefCell<String>,
+    pub layout: RefCell<RadialLayout>,
+    pub theme: RefCell<Theme>,
 }
 
 impl Default for RadialMenu {
@@ -33,6 +49,14 @@ impl Default for RadialMenu {
             labels: RefCell::new(String::new()),
             x: RefCell::new(0.0),
             y: RefCell::new(0.0),
+            selected: RefCell::new(-1),
+            breadcrumbs: RefCell::new(String::new()),
+            completions: RefCell::new(String::new()),
+            completion: RefCell::new(-1),
+            status: RefCell::new(String::new()),
+            toast: RefCell::new(String::new()),
+            layout: RefCell::new(RadialLayout::default()),
+            theme: RefCell::new(Theme::default()),
         }
     }
 }
@@ -95,15 +119,48 @@ impl GLAreaImpl for RadialMenu {
         canvas.global_composite_operation(CompositeOperation::Copy);
         canvas.clear_rect(0, 0, w, h, Color::rgba(0, 0, 0, 0));
 
+        // no radial menu, status or toast for this overlay right now
+        let labels = self.labels.borrow();
+        let status = self.status.borrow();
+        let toast = self.toast.borrow();
+        if labels.is_empty() && status.is_empty() && toast.is_empty() {
+            canvas.flush();
+            return glib::Propagation::Stop;
+        }
+        let theme = *self.theme.borrow();
+
         // puts input x/y coords at centered (0, 0)
         canvas.translate(w as f32 / 2., h as f32 / 2.);
 
+        // status like "paused" in the middle, under the ring if there is one
+        if !status.is_empty() && self.font.borrow().is_some() {
+            let paint = Paint::color(color(theme.selected))
+                .with_font(&[self.font.borrow().unwrap()])
+                .with_text_baseline(Baseline::Middle)
+                .with_text_align(Align::Center)
+                .with_font_size(w as f32 / 10.0);
+            let _ = canvas.fill_text(0.0, 0.0, status.as_str(), &paint);
+        }
+        // toast like "pad 2 connected" below the middle, clear of the ring's labels
+        if !toast.is_empty() && self.font.borrow().is_some() {
+            let paint = Paint::color(color(theme.text))
+                .with_font(&[self.font.borrow().unwrap()])
+                .with_text_baseline(Baseline::Middle)
+                .with_text_align(Align::Center)
+                .with_font_size(w as f32 / 16.0);
+            let _ = canvas.fill_text(0.0, w as f32 * 0.42, toast.as_str(), &paint);
+        }
+        if labels.is_empty() {
+            canvas.flush();
+            return glib::Propagation::Stop;
+        }
+
         // outer circle
         let outer_radius = w as f32 * 0.35;
         let mut path = Path::new();
         path.circle(0.0, 0.0, outer_radius);
         path.close();
-        let mut paint = Paint::color(Color::rgba(128, 128, 225, 128));
+        let mut paint = Paint::color(color(theme.ring));
         paint.set_line_width(4.);
         canvas.stroke_path(&path, &paint);
 
@@ -113,23 +170,86 @@ impl GLAreaImpl for RadialMenu {
         let y = -*self.y.borrow();
         path.circle(x * outer_radius, y * outer_radius, 40.0);
         path.close();
-        let mut paint = Paint::color(Color::rgba(178, 0, 225, 200));
+        let mut paint = Paint::color(color(theme.marker));
         paint.set_line_width(2.);
         canvas.stroke_path(&path, &paint);
 
+        // sector dividers, only worth drawing when sectors differ in size or start.
+        // `set_ring` reports labels that don't match their sectors.
+        let count = labels.chars().count();
+        let mut layout = self.layout.borrow().clone();
+        if layout.len() != count {
+            layout = RadialLayout::even(count);
+        }
+        if !layout.is_even() {
+            let mut path = Path::new();
+            for i in 0..count {
+                let theta = layout.boundary(i);
+                path.move_to(
+                    outer_radius * 0.75 * f32::cos(theta),
+                    -outer_radius * 0.75 * f32::sin(theta),
+                );
+                path.line_to(
+                    outer_radius * 1.25 * f32::cos(theta),
+                    -outer_radius * 1.25 * f32::sin(theta),
+                );
+            }
+            let mut paint = Paint::color(color(theme.ring));
+            paint.set_line_width(2.);
+            canvas.stroke_path(&path, &paint);
+        }
+
         if self.font.borrow().is_some() {
-            let paint = Paint::color(Color::rgb(255, 255, 255))
+            let paint = Paint::color(color(theme.text))
                 .with_font(&[self.font.borrow().unwrap()])
                 .with_text_baseline(Baseline::Middle)
                 .with_text_align(Align::Center)
                 .with_font_size(w as f32 / 8.0);
+            let selected_paint = paint.clone().with_color(color(theme.selected));
+            let selected = *self.selected.borrow();
             let txt_radius = outer_radius;
-            let len = (*self.labels.borrow()).len() as f32;
-            for (i, ch) in (*self.labels.borrow()).char_indices() {
-                let theta = std::f64::consts::TAU as f32 * (i as f32 / len);
+            for (i, ch) in labels.chars().enumerate() {
+                let theta = layout.center(i);
                 let x = txt_radius * f32::cos(theta);
                 let y = txt_radius * f32::sin(theta);
-                let _ = canvas.fill_text(x, -y, ch.to_string().as_str(), &paint);
+                let paint = if i as i32 == selected {
+                    &selected_paint
+                } else {
+                    &paint
+                };
+                let _ = canvas.fill_text(x, -y, ch.to_string().as_str(), paint);
+            }
+
+            // submenus opened to reach this ring, along the top edge
+            let breadcrumbs = self.breadcrumbs.borrow();
+            if !breadcrumbs.is_empty() {
+                let font_size = w as f32 / 16.0;
+                let paint = paint.clone().with_font_size(font_size);
+                let _ = canvas.fill_text(
+                    0.0,
+                    font_size - h as f32 / 2.0,
+                    breadcrumbs.as_str(),
+                    &paint,
+                );
+            }
+
+            // word completions in a row along the bottom edge
+            let completions = self.completions.borrow();
+            if !completions.is_empty() {
+                let font_size = w as f32 / 16.0;
+                let paint = paint.with_font_size(font_size);
+                let selected_paint = selected_paint.with_font_size(font_size);
+                let completion = *self.completion.borrow();
+                let count = completions.lines().count() as f32;
+                for (i, word) in completions.lines().enumerate() {
+                    let x = w as f32 * ((i as f32 + 0.5) / count - 0.5);
+                    let paint = if i as i32 == completion {
+                        &selected_paint
+                    } else {
+                        &paint
+                    };
+                    let _ = canvas.fill_text(x, h as f32 / 2.0 - font_size, word, paint);
+                }
             }
         }
 
@@ -180,3 +300,7 @@ impl RadialMenu {
         self.y = RefCell::new(y);
     }
 }
+
+fn color(rgba: Rgba) -> femtovg::Color {
+    femtovg::Color::rgba(rgba.0, rgba.1, rgba.2, rgba.3)
+}
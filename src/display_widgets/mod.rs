@@ -1,6 +1,10 @@
 pub mod imp;
 
 use gtk::glib;
+use gtk::subclass::prelude::*;
+
+use crate::profile::Theme;
+use crate::radial::RadialLayout;
 
 glib::wrapper! {
     pub struct RadialMenu(ObjectSubclass<imp::RadialMenu>)
@@ -19,4 +23,23 @@ impl RadialMenu {
         self.set_x(x);
         self.set_y(y);
     }
+
+    // a ring's labels and the sectors they are placed in, set together so the
+    // overlay draws the sectors the input loop selects from
+    pub fn set_ring(&self, labels: &str, layout: RadialLayout) {
+        let count = labels.chars().count();
+        if layout.len() != count {
+            println!(
+                "radial overlay got {} labels for {} sectors, drawing even sectors",
+                count,
+                layout.len()
+            );
+        }
+        self.set_labels(labels);
+        self.imp().layout.replace(layout);
+    }
+
+    pub fn set_theme(&self, theme: Theme) {
+        self.imp().theme.replace(theme);
+    }
 }
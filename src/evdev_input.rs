@@ -0,0 +1,455 @@
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use evdev::{AbsoluteAxisType, Device, EventType, InputEvent, InputEventKind, InputId, Key};
+use tokio::task::JoinHandle;
+
+use crate::input::{InputSource, InputUpdate, PadId, PadInfo, Poll};
+use crate::runtime;
+use crate::types::{BasicControllerState, CommonAnalog, CommonButton};
+
+pub fn key_to_bcs(key: Key) -> Option<CommonButton> {
+    let button = match key {
+        Key::BTN_SOUTH => CommonButton::FaceSouth,
+        Key::BTN_EAST => CommonButton::FaceEast,
+        Key::BTN_NORTH => CommonButton::FaceNorth,
+        Key::BTN_WEST => CommonButton::FaceWest,
+        Key::BTN_TL => CommonButton::LegacyLT,
+        Key::BTN_TR => CommonButton::LegacyRT,
+        Key::BTN_TL2 => CommonButton::LegacyLT2,
+        Key::BTN_TR2 => CommonButton::LegacyRT2,
+        Key::BTN_SELECT => CommonButton::Select,
+        Key::BTN_START => CommonButton::Start,
+        Key::BTN_MODE => CommonButton::Guide,
+        Key::BTN_THUMBL => CommonButton::LeftStickPress,
+        Key::BTN_THUMBR => CommonButton::RightStickPress,
+        Key::BTN_DPAD_UP => CommonButton::DPadNorth,
+        Key::BTN_DPAD_DOWN => CommonButton::DPadSouth,
+        Key::BTN_DPAD_LEFT => CommonButton::DPadWest,
+        Key::BTN_DPAD_RIGHT => CommonButton::DPadEast,
+        Key::BTN_C => CommonButton::LegacyC,
+        Key::BTN_Z => CommonButton::LegacyZ,
+        _ => return None,
+    };
+    Some(button)
+}
+
+pub fn abs_to_bcs(axis: AbsoluteAxisType) -> Option<CommonAnalog> {
+    let analog = match axis {
+        AbsoluteAxisType::ABS_X => CommonAnalog::LeftStickX,
+        AbsoluteAxisType::ABS_Y => CommonAnalog::LeftStickY,
+        AbsoluteAxisType::ABS_Z => CommonAnalog::LeftLever,
+        AbsoluteAxisType::ABS_RX => CommonAnalog::RightStickX,
+        AbsoluteAxisType::ABS_RY => CommonAnalog::RightStickY,
+        AbsoluteAxisType::ABS_RZ => CommonAnalog::RightLever,
+        _ => return None,
+    };
+    Some(analog)
+}
+
+// range of each absolute axis, indexed by axis code
+type AbsRanges = Vec<(i32, i32)>;
+
+// applies one evdev event to the controller state.
+// sticks become -1..1 with y up like gilrs, triggers 0..1, and the hat the d-pad buttons.
+pub fn apply_event(event: &InputEvent, ranges: &AbsRanges, state: &mut BasicControllerState) {
+    match event.kind() {
+        InputEventKind::Key(key) => {
+            if let Some(button) = key_to_bcs(key) {
+                // 2 is autorepeat, still held
+                state.try_update_button(button, (event.value() != 0) as i32 as f32);
+            }
+        }
+        InputEventKind::AbsAxis(axis) => {
+            let value = event.value();
+            let (negative, positive) = match axis {
+                AbsoluteAxisType::ABS_HAT0X => (CommonButton::DPadWest, CommonButton::DPadEast),
+                AbsoluteAxisType::ABS_HAT0Y => (CommonButton::DPadNorth, CommonButton::DPadSouth),
+                _ => {
+                    let Some(analog) = abs_to_bcs(axis) else {
+                        return;
+                    };
+                    let (min, max) = ranges.get(axis.0 as usize).copied().unwrap_or((-1, 1));
+                    if max <= min {
+                        return;
+                    }
+                    let t = (value - min) as f32 / (max - min) as f32;
+                    let value = match analog {
+                        CommonAnalog::LeftLever | CommonAnalog::RightLever => t,
+                        CommonAnalog::LeftStickY | CommonAnalog::RightStickY => 1.0 - 2.0 * t,
+                        _ => 2.0 * t - 1.0,
+                    };
+                    state.try_update_analog(analog, value.clamp(-1.0, 1.0));
+                    return;
+                }
+            };
+            state.try_update_button(negative, (value < 0) as i32 as f32);
+            state.try_update_button(positive, (value > 0) as i32 as f32);
+        }
+        _ => (),
+    }
+}
+
+// a pad read through evdev directly, instead of through gilrs.
+// grabbed, no other program sees its input, gilrs included.
+// dropping it closes the device, which ends the grab.
+pub struct EvdevPad {
+    id: PadId,
+    info: PadInfo,
+    ranges: AbsRanges,
+    events: Receiver<InputEvent>,
+    reader: JoinHandle<()>,
+    state: BasicControllerState,
+    // the first poll says the pad is there
+    announced: bool,
+}
+
+impl EvdevPad {
+    // the first gamepad `matches` accepts, reported as `id`
+    pub fn open(
+        id: PadId,
+        grab: bool,
+        matches: impl Fn(&Path, &Device) -> bool,
+    ) -> io::Result<Self> {
+        let (path, mut device) = evdev::enumerate()
+            .find(|(path, device)| {
+                device
+                    .supported_keys()
+                    .is_some_and(|keys| keys.contains(Key::BTN_SOUTH))
+                    && matches(path, device)
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no matching gamepad"))?;
+
+        let abs = device.get_abs_state()?;
+        let ranges = abs
+            .iter()
+            .map(|info| (info.minimum, info.maximum))
+            .collect();
+        if grab {
+            device.grab()?;
+        }
+
+        // the current state goes first, so held buttons and sticks are known right away
+        let (tx, events) = mpsc::channel();
+        for key in device.get_key_state()?.iter() {
+            let _ = tx.send(InputEvent::new(EventType::KEY, key.code(), 1));
+        }
+        if let Some(axes) = device.supported_absolute_axes() {
+            for axis in axes.iter() {
+                let value = abs[axis.0 as usize].value;
+                let _ = tx.send(InputEvent::new(EventType::ABSOLUTE, axis.0, value));
+            }
+        }
+
+        let input_id = device.input_id();
+        let info = PadInfo {
+            name: device.name().unwrap_or_default().to_string(),
+            uuid: uuid(&input_id),
+            vendor: Some(input_id.vendor()),
+            product: Some(input_id.product()),
+        };
+        let mut stream = device.into_event_stream()?;
+        let reader = runtime().spawn(async move {
+            loop {
+                match stream.next_event().await {
+                    Ok(event) => {
+                        if tx.send(event).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        println!("{}: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            id,
+            info,
+            ranges,
+            events,
+            reader,
+            state: BasicControllerState::default(),
+            announced: false,
+        })
+    }
+
+    pub fn id(&self) -> PadId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+}
+
+// the guid gilrs gives the same device: bus, vendor, product and version,
+// each little-endian and padded to four bytes
+fn uuid(id: &InputId) -> [u8; 16] {
+    let mut uuid = [0; 16];
+    let fields = [id.bus_type().0, id.vendor(), id.product(), id.version()];
+    for (chunk, field) in uuid.chunks_mut(4).zip(fields) {
+        chunk[..2].copy_from_slice(&field.to_le_bytes());
+    }
+    uuid
+}
+
+impl InputSource for EvdevPad {
+    fn poll(&mut self, timeout: Duration) -> Poll {
+        if !self.announced {
+            self.announced = true;
+            return Poll::Connected(self.id);
+        }
+        match self.events.recv_timeout(timeout) {
+            Ok(event) => apply_event(&event, &self.ranges, &mut self.state),
+            Err(RecvTimeoutError::Timeout) => return Poll::Idle,
+            Err(RecvTimeoutError::Disconnected) => return Poll::Gone(self.id),
+        }
+        while let Ok(event) = self.events.try_recv() {
+            apply_event(&event, &self.ranges, &mut self.state);
+        }
+        Poll::Update(InputUpdate {
+            pad: self.id,
+            time: Instant::now(),
+            state: self.state,
+        })
+    }
+
+    fn info(&self, _pad: PadId) -> PadInfo {
+        self.info.clone()
+    }
+}
+
+impl Drop for EvdevPad {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+// whether an evdev device is the one gilrs knows by this os name and ids
+pub fn is_gilrs_pad(
+    device: &Device,
+    name: &str,
+    vendor: Option<u16>,
+    product: Option<u16>,
+) -> bool {
+    same_pad(device.name(), &device.input_id(), name, vendor, product)
+}
+
+fn same_pad(
+    device_name: Option<&str>,
+    id: &InputId,
+    name: &str,
+    vendor: Option<u16>,
+    product: Option<u16>,
+) -> bool {
+    device_name == Some(name)
+        && vendor.is_none_or(|v| v == id.vendor())
+        && product.is_none_or(|p| p == id.product())
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev::BusType;
+
+    use super::*;
+
+    #[test]
+    fn pad_buttons_map_to_common_buttons() {
+        let table = [
+            (Key::BTN_SOUTH, CommonButton::FaceSouth),
+            (Key::BTN_EAST, CommonButton::FaceEast),
+            (Key::BTN_NORTH, CommonButton::FaceNorth),
+            (Key::BTN_WEST, CommonButton::FaceWest),
+            (Key::BTN_TL, CommonButton::LegacyLT),
+            (Key::BTN_TR, CommonButton::LegacyRT),
+            (Key::BTN_TL2, CommonButton::LegacyLT2),
+            (Key::BTN_TR2, CommonButton::LegacyRT2),
+            (Key::BTN_SELECT, CommonButton::Select),
+            (Key::BTN_START, CommonButton::Start),
+            (Key::BTN_MODE, CommonButton::Guide),
+            (Key::BTN_THUMBL, CommonButton::LeftStickPress),
+            (Key::BTN_THUMBR, CommonButton::RightStickPress),
+            (Key::BTN_DPAD_UP, CommonButton::DPadNorth),
+            (Key::BTN_DPAD_DOWN, CommonButton::DPadSouth),
+            (Key::BTN_DPAD_LEFT, CommonButton::DPadWest),
+            (Key::BTN_DPAD_RIGHT, CommonButton::DPadEast),
+            (Key::BTN_C, CommonButton::LegacyC),
+            (Key::BTN_Z, CommonButton::LegacyZ),
+        ];
+        for (key, button) in table {
+            assert_eq!(key_to_bcs(key), Some(button), "{:?}", key);
+        }
+        for key in [Key::KEY_A, Key::BTN_LEFT, Key::BTN_TRIGGER_HAPPY1] {
+            assert_eq!(key_to_bcs(key), None, "{:?}", key);
+        }
+    }
+
+    #[test]
+    fn pad_axes_map_to_common_analogs() {
+        let table = [
+            (AbsoluteAxisType::ABS_X, CommonAnalog::LeftStickX),
+            (AbsoluteAxisType::ABS_Y, CommonAnalog::LeftStickY),
+            (AbsoluteAxisType::ABS_Z, CommonAnalog::LeftLever),
+            (AbsoluteAxisType::ABS_RX, CommonAnalog::RightStickX),
+            (AbsoluteAxisType::ABS_RY, CommonAnalog::RightStickY),
+            (AbsoluteAxisType::ABS_RZ, CommonAnalog::RightLever),
+        ];
+        for (axis, analog) in table {
+            assert_eq!(abs_to_bcs(axis), Some(analog), "{:?}", axis);
+        }
+        // the hat is read as d-pad buttons
+        for axis in [
+            AbsoluteAxisType::ABS_HAT0X,
+            AbsoluteAxisType::ABS_HAT0Y,
+            AbsoluteAxisType::ABS_WHEEL,
+        ] {
+            assert_eq!(abs_to_bcs(axis), None, "{:?}", axis);
+        }
+    }
+
+    fn key(key: Key, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY, key.code(), value)
+    }
+
+    fn abs(axis: AbsoluteAxisType, value: i32) -> InputEvent {
+        InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+    }
+
+    // sticks 0..256 and triggers 0..255, like many usb pads
+    fn ranges() -> AbsRanges {
+        let mut ranges = vec![(0, 0); AbsoluteAxisType::ABS_RZ.0 as usize + 1];
+        for axis in [
+            AbsoluteAxisType::ABS_X,
+            AbsoluteAxisType::ABS_Y,
+            AbsoluteAxisType::ABS_RX,
+            AbsoluteAxisType::ABS_RY,
+        ] {
+            ranges[axis.0 as usize] = (0, 256);
+        }
+        for axis in [AbsoluteAxisType::ABS_Z, AbsoluteAxisType::ABS_RZ] {
+            ranges[axis.0 as usize] = (0, 255);
+        }
+        ranges
+    }
+
+    fn button(state: &BasicControllerState, button: CommonButton) -> f32 {
+        state.button_state_by_type(button).value
+    }
+
+    fn analog(state: &BasicControllerState, analog: CommonAnalog) -> f32 {
+        state.analog_state_by_type(analog).value
+    }
+
+    #[test]
+    fn keys_press_and_release_buttons() {
+        let mut state = BasicControllerState::default();
+        let ranges = ranges();
+        let south = CommonButton::FaceSouth;
+        apply_event(&key(Key::BTN_SOUTH, 1), &ranges, &mut state);
+        assert_eq!(button(&state, south), 1.0);
+        // autorepeat is still held
+        apply_event(&key(Key::BTN_SOUTH, 2), &ranges, &mut state);
+        assert_eq!(button(&state, south), 1.0);
+        apply_event(&key(Key::BTN_SOUTH, 0), &ranges, &mut state);
+        assert_eq!(button(&state, south), 0.0);
+        // keys that aren't pad buttons change nothing
+        apply_event(&key(Key::KEY_A, 1), &ranges, &mut state);
+        assert!(state.buttons.iter().all(|b| b.value == 0.0));
+    }
+
+    #[test]
+    fn axes_scale_to_their_range() {
+        let mut state = BasicControllerState::default();
+        let ranges = ranges();
+        let table = [
+            (AbsoluteAxisType::ABS_X, 0, CommonAnalog::LeftStickX, -1.0),
+            (AbsoluteAxisType::ABS_X, 128, CommonAnalog::LeftStickX, 0.0),
+            (AbsoluteAxisType::ABS_X, 256, CommonAnalog::LeftStickX, 1.0),
+            // y points up, like gilrs
+            (AbsoluteAxisType::ABS_Y, 0, CommonAnalog::LeftStickY, 1.0),
+            (AbsoluteAxisType::ABS_RY, 64, CommonAnalog::RightStickY, 0.5),
+            (
+                AbsoluteAxisType::ABS_RX,
+                192,
+                CommonAnalog::RightStickX,
+                0.5,
+            ),
+            (AbsoluteAxisType::ABS_Z, 0, CommonAnalog::LeftLever, 0.0),
+            (AbsoluteAxisType::ABS_RZ, 255, CommonAnalog::RightLever, 1.0),
+            // out of range is clamped
+            (AbsoluteAxisType::ABS_X, 300, CommonAnalog::LeftStickX, 1.0),
+        ];
+        for (axis, value, common, expected) in table {
+            apply_event(&abs(axis, value), &ranges, &mut state);
+            assert_eq!(analog(&state, common), expected, "{:?} {}", axis, value);
+        }
+    }
+
+    #[test]
+    fn axes_without_a_usable_range() {
+        let mut state = BasicControllerState::default();
+        // no range known, -1..1 is assumed
+        apply_event(&abs(AbsoluteAxisType::ABS_X, 1), &Vec::new(), &mut state);
+        assert_eq!(analog(&state, CommonAnalog::LeftStickX), 1.0);
+        // an empty range is skipped
+        let mut ranges = ranges();
+        ranges[AbsoluteAxisType::ABS_X.0 as usize] = (5, 5);
+        apply_event(&abs(AbsoluteAxisType::ABS_X, 5), &ranges, &mut state);
+        assert_eq!(analog(&state, CommonAnalog::LeftStickX), 1.0);
+    }
+
+    #[test]
+    fn the_hat_presses_dpad_buttons() {
+        let mut state = BasicControllerState::default();
+        let ranges = ranges();
+        let dpad = |state: &BasicControllerState| {
+            [
+                CommonButton::DPadNorth,
+                CommonButton::DPadSouth,
+                CommonButton::DPadWest,
+                CommonButton::DPadEast,
+            ]
+            .map(|b| button(state, b))
+        };
+        apply_event(&abs(AbsoluteAxisType::ABS_HAT0X, -1), &ranges, &mut state);
+        assert_eq!(dpad(&state), [0.0, 0.0, 1.0, 0.0]);
+        apply_event(&abs(AbsoluteAxisType::ABS_HAT0X, 1), &ranges, &mut state);
+        assert_eq!(dpad(&state), [0.0, 0.0, 0.0, 1.0]);
+        // hat y is down for positive
+        apply_event(&abs(AbsoluteAxisType::ABS_HAT0Y, -1), &ranges, &mut state);
+        assert_eq!(dpad(&state), [1.0, 0.0, 0.0, 1.0]);
+        apply_event(&abs(AbsoluteAxisType::ABS_HAT0Y, 1), &ranges, &mut state);
+        apply_event(&abs(AbsoluteAxisType::ABS_HAT0X, 0), &ranges, &mut state);
+        assert_eq!(dpad(&state), [0.0, 1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn gilrs_pads_match_by_name_and_known_ids() {
+        let id = InputId::new(BusType::BUS_USB, 0x054c, 0x09cc, 0x8111);
+        let ds4 = |name, vendor, product| {
+            same_pad(Some("Wireless Controller"), &id, name, vendor, product)
+        };
+        let name = "Wireless Controller";
+        assert!(ds4(name, Some(0x054c), Some(0x09cc)));
+        // ids gilrs doesn't know are left out
+        assert!(ds4(name, None, None));
+        assert!(!ds4(name, Some(0x045e), None));
+        assert!(!ds4(name, None, Some(0x02ea)));
+        assert!(!ds4("Xbox Controller", None, None));
+        // a device without a name matches nothing
+        assert!(!same_pad(None, &id, name, None, None));
+    }
+
+    #[test]
+    fn uuids_are_laid_out_like_gilrs() {
+        let id = InputId::new(BusType::BUS_USB, 0x054c, 0x09cc, 0x8111);
+        assert_eq!(
+            uuid(&id),
+            [0x03, 0, 0, 0, 0x4c, 0x05, 0, 0, 0xcc, 0x09, 0, 0, 0x11, 0x81, 0, 0]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::time::{Duration, Instant};
+
+use crate::binding::BUTTON_PRESS;
+use crate::input::PadInput;
+use crate::types::{BasicControllerState, CommonButton};
+
+// a pad's whole state, and when it was read
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub time: Instant,
+    pub state: BasicControllerState,
+}
+
+// what happened between two frames. buttons press and release where they cross
+// BUTTON_PRESS, and every button or analog whose value moved at all is changed,
+// with its new value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FrameDiff {
+    pub elapsed: Duration,
+    pub pressed: Vec<CommonButton>,
+    pub released: Vec<CommonButton>,
+    pub changed: Vec<(PadInput, f32)>,
+}
+
+pub fn diff(prev: &Frame, next: &Frame) -> FrameDiff {
+    let mut diff = FrameDiff {
+        elapsed: next.time.saturating_duration_since(prev.time),
+        ..FrameDiff::default()
+    };
+    for (prev, next) in prev.state.buttons.iter().zip(next.state.buttons.iter()) {
+        if prev.value == next.value {
+            continue;
+        }
+        match (prev.value > BUTTON_PRESS, next.value > BUTTON_PRESS) {
+            (false, true) => diff.pressed.push(next.ty),
+            (true, false) => diff.released.push(next.ty),
+            _ => (),
+        }
+        diff.changed.push((PadInput::Button(next.ty), next.value));
+    }
+    for (prev, next) in prev.state.analogs.iter().zip(next.state.analogs.iter()) {
+        if prev.value != next.value {
+            diff.changed.push((PadInput::Analog(next.ty), next.value));
+        }
+    }
+    diff
+}
+
+// the frames of one pad as they come in, and when each of its buttons went down.
+// time only moves forward, a frame stamped before the last one counts as at the same time.
+#[derive(Default)]
+pub struct Frames {
+    // none before the first frame, the pad is taken to be at rest until then
+    last: Option<Frame>,
+    // by position in BasicControllerState::buttons
+    pressed_at: Vec<Option<Instant>>,
+}
+
+impl Frames {
+    pub fn push(&mut self, state: BasicControllerState, time: Instant) -> FrameDiff {
+        let prev = self.last.unwrap_or(Frame {
+            time,
+            state: BasicControllerState::default(),
+        });
+        let next = Frame {
+            time: time.max(prev.time),
+            state,
+        };
+        self.pressed_at.resize(state.buttons.len(), None);
+        for (at, button) in self.pressed_at.iter_mut().zip(state.buttons.iter()) {
+            *at = match button.value > BUTTON_PRESS {
+                true => at.or(Some(next.time)),
+                false => None,
+            };
+        }
+        self.last = Some(next);
+        diff(&prev, &next)
+    }
+
+    // how long the button has been down as of the last frame, none while it is up
+    pub fn held_for(&self, button: CommonButton) -> Option<Duration> {
+        let last = self.last.as_ref()?;
+        let index = last.state.buttons.iter().position(|b| b.ty == button)?;
+        let at = self.pressed_at[index]?;
+        Some(last.time.duration_since(at))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::CommonAnalog;
+
+    // a pad's frames, each pushed some ms after the start
+    struct Pad {
+        frames: Frames,
+        state: BasicControllerState,
+        start: Instant,
+    }
+
+    impl Pad {
+        fn new() -> Self {
+            Self {
+                frames: Frames::default(),
+                state: BasicControllerState::default(),
+                start: Instant::now(),
+            }
+        }
+
+        // the button moves to `value` and the pad is pushed `ms` after the start
+        fn set(&mut self, button: CommonButton, value: f32, ms: u64) -> FrameDiff {
+            self.state.try_update_button(button, value);
+            self.push(ms)
+        }
+
+        fn push(&mut self, ms: u64) -> FrameDiff {
+            self.frames
+                .push(self.state, self.start + Duration::from_millis(ms))
+        }
+    }
+
+    #[test]
+    fn buttons_press_and_release_past_button_press() {
+        let mut pad = Pad::new();
+        let south = CommonButton::FaceSouth;
+        let diff = pad.set(south, BUTTON_PRESS, 0);
+        assert!(diff.pressed.is_empty());
+        assert_eq!(diff.changed, vec![(PadInput::Button(south), BUTTON_PRESS)]);
+        let diff = pad.set(south, 0.51, 10);
+        assert_eq!(diff.pressed, vec![south]);
+        assert_eq!(diff.elapsed, Duration::from_millis(10));
+        let diff = pad.set(south, 0.8, 20);
+        assert!(diff.pressed.is_empty() && diff.released.is_empty());
+        let diff = pad.set(south, BUTTON_PRESS, 30);
+        assert_eq!(diff.released, vec![south]);
+    }
+
+    #[test]
+    fn analogs_only_change() {
+        let mut pad = Pad::new();
+        pad.push(0);
+        pad.state.try_update_analog(CommonAnalog::LeftStickX, 0.9);
+        let diff = pad.push(10);
+        assert!(diff.pressed.is_empty() && diff.released.is_empty());
+        assert_eq!(
+            diff.changed,
+            vec![(PadInput::Analog(CommonAnalog::LeftStickX), 0.9)]
+        );
+        // nothing moved, nothing changed
+        assert_eq!(pad.push(20).changed, vec![]);
+    }
+
+    #[test]
+    fn held_for_counts_from_the_press() {
+        let mut pad = Pad::new();
+        let (east, south) = (CommonButton::FaceEast, CommonButton::FaceSouth);
+        assert_eq!(pad.frames.held_for(east), None);
+        pad.set(east, 1.0, 100);
+        assert_eq!(pad.frames.held_for(east), Some(Duration::ZERO));
+        pad.set(south, 1.0, 350);
+        assert_eq!(pad.frames.held_for(east), Some(Duration::from_millis(250)));
+        pad.set(east, 0.0, 400);
+        assert_eq!(pad.frames.held_for(east), None);
+        assert_eq!(pad.frames.held_for(south), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn frames_stamped_before_the_last_count_as_at_the_same_time() {
+        let mut pad = Pad::new();
+        let (east, north) = (CommonButton::FaceEast, CommonButton::FaceNorth);
+        pad.set(east, 1.0, 100);
+        pad.set(CommonButton::FaceSouth, 1.0, 200);
+        let diff = pad.set(north, 1.0, 150);
+        assert_eq!(diff.elapsed, Duration::ZERO);
+        assert_eq!(pad.frames.held_for(east), Some(Duration::from_millis(100)));
+        assert_eq!(pad.frames.held_for(north), Some(Duration::ZERO));
+        assert_eq!(pad.set(east, 0.0, 210).elapsed, Duration::from_millis(10));
+    }
+}
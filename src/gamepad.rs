@@ -0,0 +1,181 @@
+use std::io;
+
+use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
+use evdev::{
+    AbsInfo, AbsoluteAxisType, AttributeSet, BusType, EventType, InputEvent, InputId, Key,
+    UinputAbsSetup,
+};
+
+use crate::binding::{Claims, BUTTON_PRESS};
+use crate::output::OutputSink;
+use crate::profile::Gamepad;
+use crate::radial::RadialSource;
+use crate::types::{BasicControllerState, CommonAnalog, CommonButton};
+
+pub const DEFAULT_GAMEPAD_NAME: &str = "padmixer gamepad";
+
+// buttons the virtual gamepad has. the d-pad is a hat and the triggers are axes.
+const PAD_KEYS: [(CommonButton, Key); 11] = [
+    (CommonButton::FaceSouth, Key::BTN_SOUTH),
+    (CommonButton::FaceEast, Key::BTN_EAST),
+    (CommonButton::FaceNorth, Key::BTN_NORTH),
+    (CommonButton::FaceWest, Key::BTN_WEST),
+    (CommonButton::LegacyLT, Key::BTN_TL),
+    (CommonButton::LegacyRT, Key::BTN_TR),
+    (CommonButton::Select, Key::BTN_SELECT),
+    (CommonButton::Start, Key::BTN_START),
+    (CommonButton::Guide, Key::BTN_MODE),
+    (CommonButton::LeftStickPress, Key::BTN_THUMBL),
+    (CommonButton::RightStickPress, Key::BTN_THUMBR),
+];
+
+const STICK_MAX: i32 = 32767;
+const TRIGGER_MAX: i32 = 255;
+
+// a uinput gamepad laid out like an xbox 360 pad, which games know how to read
+pub fn device(name: &str) -> io::Result<VirtualDevice> {
+    let mut keys = AttributeSet::<Key>::new();
+    for (_, key) in PAD_KEYS {
+        keys.insert(key);
+    }
+    let stick = AbsInfo::new(0, -STICK_MAX - 1, STICK_MAX, 16, 128, 0);
+    let trigger = AbsInfo::new(0, 0, TRIGGER_MAX, 0, 0, 0);
+    let hat = AbsInfo::new(0, -1, 1, 0, 0, 0);
+    let mut builder = VirtualDeviceBuilder::new()?
+        .name(name)
+        .input_id(InputId::new(BusType::BUS_USB, 0x045e, 0x028e, 0x110))
+        .with_keys(&keys)?;
+    for (axis, info) in [
+        (AbsoluteAxisType::ABS_X, stick),
+        (AbsoluteAxisType::ABS_Y, stick),
+        (AbsoluteAxisType::ABS_RX, stick),
+        (AbsoluteAxisType::ABS_RY, stick),
+        (AbsoluteAxisType::ABS_Z, trigger),
+        (AbsoluteAxisType::ABS_RZ, trigger),
+        (AbsoluteAxisType::ABS_HAT0X, hat),
+        (AbsoluteAxisType::ABS_HAT0Y, hat),
+    ] {
+        builder = builder.with_absolute_axis(&UinputAbsSetup::new(axis, info))?;
+    }
+    builder.build()
+}
+
+// copies the processed pad state onto the virtual gamepad.
+// inputs the active layers claim rest at zero, so games only see what is left over.
+// only values that changed since the last frame are sent.
+#[derive(Default)]
+pub struct GamepadMirror {
+    last: Option<Vec<InputEvent>>,
+}
+
+impl GamepadMirror {
+    pub fn update(
+        &mut self,
+        config: &Gamepad,
+        claims: &Claims,
+        state: &BasicControllerState,
+        out: &mut dyn OutputSink,
+    ) {
+        let mut visible = *state;
+        for button in visible.buttons.iter_mut() {
+            if claims.buttons.contains(&button.ty) {
+                button.value = 0.0;
+            }
+        }
+        for analog in visible.analogs.iter_mut() {
+            if claims.analogs.contains(&analog.ty) {
+                analog.value = 0.0;
+            }
+        }
+
+        // remapped buttons, two buttons onto one read as either
+        let mut mirrored = visible;
+        for button in mirrored.buttons.iter_mut() {
+            button.value = 0.0;
+        }
+        for button in visible.buttons.iter() {
+            let ty = config.remap.get(&button.ty).copied().unwrap_or(button.ty);
+            let value = mirrored.button_state_by_type(ty).value.max(button.value);
+            mirrored.try_update_button(ty, value);
+        }
+
+        self.send(events(&mirrored), out);
+    }
+
+    // puts every button and axis back at rest
+    pub fn release(&mut self, out: &mut dyn OutputSink) {
+        self.send(events(&BasicControllerState::default()), out);
+    }
+
+    fn send(&mut self, next: Vec<InputEvent>, out: &mut dyn OutputSink) {
+        let changed: Vec<InputEvent> = match &self.last {
+            Some(last) => next
+                .iter()
+                .zip(last.iter())
+                .filter(|(next, last)| next.value() != last.value())
+                .map(|(next, _)| *next)
+                .collect(),
+            None => next.clone(),
+        };
+        if !changed.is_empty() {
+            out.send(&changed);
+        }
+        self.last = Some(next);
+    }
+}
+
+// the whole virtual gamepad state, always in the same order
+fn events(state: &BasicControllerState) -> Vec<InputEvent> {
+    let button = |button| state.button_state_by_type(button).value;
+    let analog = |analog| state.analog_state_by_type(analog).value;
+
+    let mut events: Vec<InputEvent> = PAD_KEYS
+        .iter()
+        .map(|(button_ty, key)| {
+            let down = button(*button_ty) > BUTTON_PRESS;
+            InputEvent::new(EventType::KEY, key.code(), down as i32)
+        })
+        .collect();
+    // evdev y axes grow downwards.
+    // pads report analog triggers either as an axis or as a pressure sensitive button.
+    let (hat_x, hat_y) = RadialSource::DPad.position(state);
+    let axes = [
+        (
+            AbsoluteAxisType::ABS_X,
+            analog(CommonAnalog::LeftStickX),
+            STICK_MAX,
+        ),
+        (
+            AbsoluteAxisType::ABS_Y,
+            -analog(CommonAnalog::LeftStickY),
+            STICK_MAX,
+        ),
+        (
+            AbsoluteAxisType::ABS_RX,
+            analog(CommonAnalog::RightStickX),
+            STICK_MAX,
+        ),
+        (
+            AbsoluteAxisType::ABS_RY,
+            -analog(CommonAnalog::RightStickY),
+            STICK_MAX,
+        ),
+        (
+            AbsoluteAxisType::ABS_Z,
+            analog(CommonAnalog::LeftLever).max(button(CommonButton::LegacyLT2)),
+            TRIGGER_MAX,
+        ),
+        (
+            AbsoluteAxisType::ABS_RZ,
+            analog(CommonAnalog::RightLever).max(button(CommonButton::LegacyRT2)),
+            TRIGGER_MAX,
+        ),
+        (AbsoluteAxisType::ABS_HAT0X, hat_x, 1),
+        (AbsoluteAxisType::ABS_HAT0Y, -hat_y, 1),
+    ];
+    events.extend(axes.into_iter().map(|(axis, value, max)| {
+        let value = (value * max as f32).round() as i32;
+        InputEvent::new(EventType::ABSOLUTE, axis.0, value)
+    }));
+    events
+}
@@ -0,0 +1,437 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gilrs::{Gamepad, GamepadId, Gilrs, GilrsBuilder};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+
+use crate::types::{axis_to_bcs, button_to_bcs, BasicControllerState, CommonAnalog, CommonButton};
+
+// one pad for as long as it stays connected, as numbered by its source
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PadId(pub usize);
+
+// what a pad says about itself, for picking its profile
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PadInfo {
+    pub name: String,
+    // sdl-style guid
+    pub uuid: [u8; 16],
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+}
+
+// the whole state of a pad after some input arrived, and when it was read
+pub struct InputUpdate {
+    pub pad: PadId,
+    pub time: Instant,
+    pub state: BasicControllerState,
+}
+
+// polled once per frame and matched right away, so the size doesn't matter
+#[allow(clippy::large_enum_variant)]
+pub enum Poll {
+    // a pad showed up, before any of its input
+    Connected(PadId),
+    Update(InputUpdate),
+    // nothing arrived in time
+    Idle,
+    // the pad went away or the recording ran out, and everything it held is let go
+    Gone(PadId),
+}
+
+// somewhere pad input comes from
+pub trait InputSource {
+    // waits up to `timeout` for input and applies everything that arrived.
+    // input from several pads comes out one pad per call.
+    fn poll(&mut self, timeout: Duration) -> Poll;
+
+    fn info(&self, pad: PadId) -> PadInfo;
+}
+
+// pads as gilrs sees them
+pub struct GilrsSource {
+    gilrs: Gilrs,
+    // pads with these names are left alone, like our own virtual gamepads
+    ignore: Vec<String>,
+    current: Option<GamepadId>,
+    states: HashMap<GamepadId, BasicControllerState>,
+    // polled but not handed out yet
+    pending: VecDeque<Poll>,
+}
+
+impl GilrsSource {
+    // the error is boxed, gilrs' own is large
+    pub fn new(ignore: Vec<String>) -> Result<Self, Box<gilrs::Error>> {
+        let mut source = Self {
+            gilrs: GilrsBuilder::new()
+                .set_update_state(false)
+                .build()
+                .map_err(Box::new)?,
+            ignore,
+            current: None,
+            states: HashMap::new(),
+            pending: VecDeque::new(),
+        };
+        // pads plugged in before padmixer started
+        let ids: Vec<GamepadId> = source.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in ids {
+            source.connect(id);
+        }
+        Ok(source)
+    }
+
+    // announces a pad the first time it is seen
+    fn connect(&mut self, id: GamepadId) {
+        let gamepad = self.gilrs.gamepad(id);
+        if self.ignore.iter().any(|name| name == gamepad.os_name()) {
+            return;
+        }
+        if let Entry::Vacant(entry) = self.states.entry(id) {
+            entry.insert(BasicControllerState::default());
+            self.pending.push_back(Poll::Connected(PadId(id.into())));
+        }
+    }
+
+    pub fn ignore(&mut self, names: Vec<String>) {
+        self.ignore = names;
+    }
+
+    // the pad input last came from
+    pub fn current(&self) -> Option<Gamepad<'_>> {
+        self.current.map(|id| self.gilrs.gamepad(id))
+    }
+}
+
+impl InputSource for GilrsSource {
+    fn poll(&mut self, timeout: Duration) -> Poll {
+        if let Some(poll) = self.pending.pop_front() {
+            return poll;
+        }
+        let mut changed = Vec::new();
+        let mut next_event = self.gilrs.next_event_blocking(Some(timeout));
+        while let Some(event) = next_event {
+            next_event = self.gilrs.next_event();
+            self.gilrs.update(&event);
+            let gamepad = self.gilrs.gamepad(event.id);
+            if self.ignore.iter().any(|name| name == gamepad.os_name()) {
+                continue;
+            }
+            match event.event {
+                gilrs::EventType::Disconnected => {
+                    if self.current == Some(event.id) {
+                        self.current = None;
+                    }
+                    if self.states.remove(&event.id).is_some() {
+                        changed.retain(|id| *id != event.id);
+                        self.pending.push_back(Poll::Gone(PadId(event.id.into())));
+                    }
+                    continue;
+                }
+                // an event a filter threw away, the pad itself is still there
+                gilrs::EventType::Dropped => continue,
+                gilrs::EventType::Connected => {
+                    self.connect(event.id);
+                    continue;
+                }
+                // pads whose connection wasn't seen are announced with their first input
+                _ => self.connect(event.id),
+            }
+            self.current = Some(event.id);
+            let Some(state) = self.states.get_mut(&event.id) else {
+                continue;
+            };
+            match event.event {
+                gilrs::EventType::ButtonPressed(button, _code) => {
+                    state.try_update_button(button_to_bcs(button), 1.0)
+                }
+                gilrs::EventType::ButtonRepeated(button, _code) => {
+                    state.try_update_button(button_to_bcs(button), 1.0)
+                }
+                gilrs::EventType::ButtonReleased(button, _code) => {
+                    state.try_update_button(button_to_bcs(button), 0.0)
+                }
+                gilrs::EventType::ButtonChanged(button, value, _code) => {
+                    state.try_update_button(button_to_bcs(button), value)
+                }
+                gilrs::EventType::AxisChanged(axis, value, _code) => {
+                    state.try_update_analog(axis_to_bcs(axis), value)
+                }
+                // force feedback and the like leave the state alone
+                _ => continue,
+            }
+            if !changed.contains(&event.id) {
+                changed.push(event.id);
+            }
+        }
+        let time = Instant::now();
+        for id in changed {
+            self.pending.push_back(Poll::Update(InputUpdate {
+                pad: PadId(id.into()),
+                time,
+                state: self.states[&id],
+            }));
+        }
+        self.pending.pop_front().unwrap_or(Poll::Idle)
+    }
+
+    fn info(&self, pad: PadId) -> PadInfo {
+        let id = self.states.keys().find(|id| usize::from(**id) == pad.0);
+        let Some(gamepad) = id.map(|id| self.gilrs.gamepad(*id)) else {
+            return PadInfo::default();
+        };
+        PadInfo {
+            name: gamepad.os_name().to_string(),
+            uuid: gamepad.uuid(),
+            vendor: gamepad.vendor_id(),
+            product: gamepad.product_id(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PadInput {
+    Button(CommonButton),
+    Analog(CommonAnalog),
+}
+
+// one input changing, `at` after the recording started
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PadEvent {
+    pub at: Duration,
+    pub input: PadInput,
+    pub value: f32,
+}
+
+impl PadEvent {
+    // a line like "120 FaceSouth 1", milliseconds first
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [at, name, value] = fields[..] else {
+            return Err("expected <ms> <button or analog> <value>".to_string());
+        };
+        let at = at
+            .parse::<u64>()
+            .map_err(|e| format!("time {}: {}", at, e))?;
+        let input = parse_input(name).ok_or_else(|| format!("{} is no button or analog", name))?;
+        let value = value
+            .parse::<f32>()
+            .map_err(|e| format!("value {}: {}", value, e))?;
+        Ok(Self {
+            at: Duration::from_millis(at),
+            input,
+            value,
+        })
+    }
+
+    pub fn apply(&self, state: &mut BasicControllerState) {
+        match self.input {
+            PadInput::Button(button) => state.try_update_button(button, self.value),
+            PadInput::Analog(analog) => state.try_update_analog(analog, self.value),
+        }
+    }
+}
+
+impl fmt::Display for PadEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let at = self.at.as_millis();
+        match self.input {
+            PadInput::Button(button) => write!(f, "{} {:?} {}", at, button, self.value),
+            PadInput::Analog(analog) => write!(f, "{} {:?} {}", at, analog, self.value),
+        }
+    }
+}
+
+// names as the profile spells them
+fn parse_input(name: &str) -> Option<PadInput> {
+    let de = || -> StrDeserializer<ValueError> { name.into_deserializer() };
+    if let Ok(button) = CommonButton::deserialize(de()) {
+        return Some(PadInput::Button(button));
+    }
+    CommonAnalog::deserialize(de()).ok().map(PadInput::Analog)
+}
+
+// a session is one pad
+const REPLAY_PAD: PadId = PadId(0);
+
+// a recorded session played back at its own pace, starting on the first poll.
+// blank lines and lines starting with # are skipped.
+pub struct ReplaySource {
+    events: VecDeque<PadEvent>,
+    start: Option<Instant>,
+    state: BasicControllerState,
+}
+
+impl ReplaySource {
+    pub fn new(mut events: Vec<PadEvent>) -> Self {
+        events.sort_by_key(|event| event.at);
+        Self {
+            events: events.into(),
+            start: None,
+            state: BasicControllerState::default(),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(read_session(path)?))
+    }
+
+    // what a poll at `now` returns, and how long it waits first. the session
+    // starts at the first poll, and nothing here reads the clock.
+    fn poll_at(&mut self, now: Instant, timeout: Duration) -> (Duration, Poll) {
+        // the session's pad is there from the first poll on
+        let Some(start) = self.start else {
+            self.start = Some(now);
+            return (Duration::ZERO, Poll::Connected(REPLAY_PAD));
+        };
+        let Some(next) = self.events.front() else {
+            return (Duration::ZERO, Poll::Gone(REPLAY_PAD));
+        };
+        let due = start + next.at;
+        if due > now + timeout {
+            return (timeout, Poll::Idle);
+        }
+
+        // everything recorded at the same moment arrives together
+        let at = next.at;
+        while let Some(event) = self.events.front() {
+            if event.at > at {
+                break;
+            }
+            event.apply(&mut self.state);
+            self.events.pop_front();
+        }
+        let poll = Poll::Update(InputUpdate {
+            pad: REPLAY_PAD,
+            time: start + at,
+            state: self.state,
+        });
+        (due.saturating_duration_since(now), poll)
+    }
+}
+
+// every event in a session file
+pub fn read_session(path: &Path) -> io::Result<Vec<PadEvent>> {
+    let src = fs::read_to_string(path)?;
+    let mut events = Vec::new();
+    for (number, line) in src.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let event = PadEvent::parse(line).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {}", path.display(), number + 1, e),
+            )
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+impl InputSource for ReplaySource {
+    fn poll(&mut self, timeout: Duration) -> Poll {
+        let (wait, poll) = self.poll_at(Instant::now(), timeout);
+        thread::sleep(wait);
+        poll
+    }
+
+    fn info(&self, _pad: PadId) -> PadInfo {
+        PadInfo {
+            name: "replay".to_string(),
+            ..PadInfo::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(name: &str, src: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("padmixer_{}_{}.txt", name, std::process::id()));
+        fs::write(&path, src).unwrap();
+        path
+    }
+
+    #[test]
+    fn event_lines_round_trip() {
+        let event = PadEvent::parse("120 FaceSouth 1").unwrap();
+        assert_eq!(event.at, Duration::from_millis(120));
+        assert_eq!(event.input, PadInput::Button(CommonButton::FaceSouth));
+        assert_eq!(event.to_string(), "120 FaceSouth 1");
+        let event = PadEvent::parse("5 LeftStickX -0.5").unwrap();
+        assert_eq!(event.to_string(), "5 LeftStickX -0.5");
+        assert!(PadEvent::parse("5 LeftStickX").is_err());
+        assert!(PadEvent::parse("x FaceSouth 1").is_err());
+    }
+
+    #[test]
+    fn replay_plays_a_session_in_order() {
+        let path = session(
+            "replay",
+            "# a session\n\n30 FaceSouth 0\n0 FaceSouth 1\n0 LeftStickX -0.5\n",
+        );
+        let mut replay = ReplaySource::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let timeout = Duration::from_millis(5);
+        assert_eq!(replay.info(REPLAY_PAD).name, "replay");
+
+        assert!(matches!(
+            replay.poll_at(start, timeout),
+            (Duration::ZERO, Poll::Connected(REPLAY_PAD))
+        ));
+
+        // everything at 0 ms arrives as one update, right away
+        let (wait, Poll::Update(first)) = replay.poll_at(start, timeout) else {
+            panic!("expected the events at 0 ms");
+        };
+        assert_eq!(wait, Duration::ZERO);
+        assert_eq!(first.time, start);
+        let button = |update: &InputUpdate| {
+            update
+                .state
+                .button_state_by_type(CommonButton::FaceSouth)
+                .value
+        };
+        assert_eq!(button(&first), 1.0);
+        let stick = first.state.analog_state_by_type(CommonAnalog::LeftStickX);
+        assert_eq!(stick.value, -0.5);
+
+        // the next event is not due within the timeout, so the whole timeout goes by
+        let (wait, poll) = replay.poll_at(at(10), timeout);
+        assert_eq!(wait, timeout);
+        assert!(matches!(poll, Poll::Idle));
+        // due within it, the poll waits until the event is
+        let (wait, Poll::Update(second)) = replay.poll_at(at(26), timeout) else {
+            panic!("expected the event at 30 ms");
+        };
+        assert_eq!(wait, Duration::from_millis(4));
+        assert_eq!(second.time, at(30));
+        assert_eq!(button(&second), 0.0);
+        assert!(matches!(
+            replay.poll_at(at(30), timeout).1,
+            Poll::Gone(REPLAY_PAD)
+        ));
+    }
+
+    #[test]
+    fn broken_session_lines_are_reported() {
+        let path = session("broken", "0 FaceSouth 1\n10 Nope 1\n");
+        let e = ReplaySource::load(&path).err().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().ends_with(":2: Nope is no button or analog"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::time::Duration;
+
+use crate::frames::Frames;
+use crate::profile::KillChord;
+
+// how long the chord is held before it pauses or resumes
+pub const DEFAULT_KILL_SWITCH_HOLD_MS: u64 = 2000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Toggle {
+    Paused,
+    Resumed,
+}
+
+// pauses and resumes all output when its chord is held on the raw pad.
+// it reads nothing but the chord, so a profile whose bindings went wrong
+// can always be stopped. the pause itself is shared by every pad.
+#[derive(Default)]
+pub struct KillSwitch {
+    // this hold already toggled, the chord has to be let go first
+    toggled: bool,
+}
+
+impl KillSwitch {
+    pub fn update(&mut self, chord: &KillChord, frames: &Frames, paused: bool) -> Option<Toggle> {
+        // the chord is held for as long as the last of its buttons to go down
+        let held_for = chord
+            .buttons
+            .iter()
+            .map(|button| frames.held_for(*button))
+            .collect::<Option<Vec<Duration>>>()
+            .and_then(|held| held.into_iter().min());
+        let Some(held_for) = held_for else {
+            self.toggled = false;
+            return None;
+        };
+        if self.toggled || held_for < Duration::from_millis(chord.hold_ms) {
+            return None;
+        }
+        self.toggled = true;
+        Some(match paused {
+            true => Toggle::Resumed,
+            false => Toggle::Paused,
+        })
+    }
+
+    // the chord that just toggled is still down, the pad sends nothing until it is let go
+    pub fn holding(&self) -> bool {
+        self.toggled
+    }
+}
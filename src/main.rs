@@ -1,28 +1,80 @@
 use std::borrow::BorrowMut;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
 use std::ptr;
-use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use gilrs::ev::state::GamepadState;
 use gtk::gdk::Display;
 use include_dir::{include_dir, Dir};
 use tokio::runtime::Runtime;
 
-use evdev::uinput::{VirtualDevice, VirtualDeviceBuilder};
-use evdev::{AttributeSet, EventType, InputEvent, Key};
-use gilrs::{Axis, Button, Gamepad, GilrsBuilder};
+use evdev::uinput::VirtualDeviceBuilder;
+use evdev::{AttributeSet, Key, RelativeAxisType};
 
 use gtk::gio::{glib, prelude::*};
 use gtk::{prelude::*, Application, CssProvider};
 use gtk4_layer_shell::{Edge, Layer, LayerShell};
 
+mod analog;
+
+mod binding;
+
 mod display_widgets;
 use display_widgets::RadialMenu;
 
+mod evdev_input;
+use evdev_input::{is_gilrs_pad, EvdevPad};
+
+mod frames;
+
+mod gamepad;
+use gamepad::GamepadMirror;
+
+mod input;
+
+mod kill_switch;
+use input::{GilrsSource, InputSource, PadId, PadInfo, Poll, ReplaySource};
+
+mod mapper;
+use mapper::{status_key, toast_key, Mapper, Outputs, FRAME_INTERVAL};
+
+mod output;
+use output::{BoxedSink, KeyTracker, PrintSink};
+
+mod pads;
+use pads::{Pad, Profiles, Toasts};
+
+mod pointer;
+
+mod predict;
+
+mod profile;
+use profile::{Anchor, Backend, Overlay, Profile};
+
+mod radial;
+use radial::{RadialLayout, RadialSource};
+
+mod release;
+use release::{release_for_exit, ReleaseOnDrop, SharedOutputs};
+
+mod session;
+use session::SessionRecorder;
+
 mod types;
-use types::{axis_to_bcs, button_to_bcs, BasicControllerState, CommonButton, ValueStore};
+use types::{BasicControllerState, ValueStore};
 
 const APP_ID: &str = "bug.junelva.padmixer";
 static RES: Dir = include_dir!("$CARGO_MANIFEST_DIR/res");
+// linux signal numbers, for glib
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+// how often a pad that isn't there yet is looked for through evdev
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+// how often the ui looks for pads that need overlays
+const OVERLAY_INTERVAL: Duration = Duration::from_millis(250);
 
 fn runtime() -> &'static Runtime {
     static RUNTIME: OnceLock<Runtime> = OnceLock::new();
@@ -30,6 +82,11 @@ fn runtime() -> &'static Runtime {
 }
 
 fn main() -> glib::ExitCode {
+    // a recorded session checked against its golden file, without a pad, display or uinput
+    if let Some(golden) = std::env::var_os("PADMIXER_GOLDEN") {
+        return golden_check(Path::new(&golden));
+    }
+
     // platform-specific injections of libepoxy which binds the glarea for rendering
     #[cfg(target_os = "macos")]
     let library = unsafe { libloading::os::unix::Library::new("libepoxy.0.dylib") }.unwrap();
@@ -45,206 +102,371 @@ fn main() -> glib::ExitCode {
             .unwrap_or(ptr::null())
     });
 
-    // prepare data sending
-    let bcs = BasicControllerState::default();
-    let bcs = RwLock::new(bcs);
+    // bindings come from the user's profile, or the bundled default.
+    // the profile file is watched so edits apply without a restart.
+    let default_profile = profile::load(
+        RES.get_file("profile.toml")
+            .unwrap()
+            .contents_utf8()
+            .unwrap(),
+    );
+    let reloads = profile::profile_path().map(profile::watch);
+    // pads can be given profiles of their own
+    let rules = pads::load();
 
-    // prepare virtual keyboard (prototype style)
+    // prepare virtual keyboard (prototype style).
+    // every regular keyboard key is registered so a reloaded profile can use any of them.
     let mut keyset = AttributeSet::<Key>::new();
-    keyset.insert(Key::KEY_1);
-    keyset.insert(Key::KEY_2);
-    keyset.insert(Key::KEY_H);
-    keyset.insert(Key::KEY_M);
-    keyset.insert(Key::KEY_N);
-    keyset.insert(Key::KEY_ESC);
-    keyset.insert(Key::KEY_LEFTSHIFT);
-    keyset.insert(Key::KEY_LEFTCTRL);
-    keyset.insert(Key::KEY_LEFTALT);
-    let mut keys = [
-        // ("d", Key::KEY_D, (0.0, 0.0)),
-        // ("w", Key::KEY_W, (0.0, 0.0)),
-        // ("a", Key::KEY_A, (0.0, 0.0)),
-        // ("s", Key::KEY_S, (0.0, 0.0)),
-        ("_", Key::KEY_SPACE, (0.0, 0.0)),
-        ("j", Key::KEY_J, (0.0, 0.0)),
-        ("k", Key::KEY_K, (0.0, 0.0)),
-        ("y", Key::KEY_Y, (0.0, 0.0)),
-        ("u", Key::KEY_U, (0.0, 0.0)),
-        ("i", Key::KEY_I, (0.0, 0.0)),
-        ("o", Key::KEY_O, (0.0, 0.0)),
-        ("p", Key::KEY_P, (0.0, 0.0)),
-    ];
-    let mut keys_string = String::new();
-    let len = keys.len() as f32;
-    for (i, key) in keys.iter_mut().enumerate() {
-        keys_string.push_str(key.0);
-        keyset.insert(key.1);
-        let theta = std::f64::consts::TAU as f32 * (i as f32 / len);
-        let x = f32::cos(theta);
-        let y = f32::sin(theta);
-        key.2 = (x, y);
+    for code in Key::KEY_ESC.code()..Key::BTN_0.code() {
+        keyset.insert(Key::new(code));
+    }
+    for code in Key::BTN_LEFT.code()..=Key::BTN_TASK.code() {
+        keyset.insert(Key::new(code));
+    }
+    for key in default_profile.keys() {
+        keyset.insert(key);
     }
-    let mut vd = VirtualDeviceBuilder::new()
-        .expect("vd new")
-        .name("USB-HID Keyboard")
-        .with_keys(&keyset)
-        .expect("vd with_keys")
-        .build()
-        .expect("vd build");
-
-    let mut store = ValueStore::new();
-    let radial_x = store.insert("radial_x", 0.0);
-    let radial_y = store.insert("radial_y", 0.0);
-    let arc_store = Arc::new(Mutex::new(store));
+    let mut relset = AttributeSet::<RelativeAxisType>::new();
+    relset.insert(RelativeAxisType::REL_X);
+    relset.insert(RelativeAxisType::REL_Y);
+    relset.insert(RelativeAxisType::REL_WHEEL);
+    relset.insert(RelativeAxisType::REL_HWHEEL);
+    // a dry run prints what would be sent instead of creating uinput devices
+    let dry_run = std::env::var_os("PADMIXER_DRY_RUN").is_some();
+    let vd: BoxedSink = if dry_run {
+        Box::new(PrintSink::new("keyboard"))
+    } else {
+        Box::new(
+            VirtualDeviceBuilder::new()
+                .expect("vd new")
+                .name("USB-HID Keyboard")
+                .with_keys(&keyset)
+                .expect("vd with_keys")
+                .with_relative_axes(&relset)
+                .expect("vd with_relative_axes")
+                .build()
+                .expect("vd build"),
+        )
+    };
+    // the stick-driven cursor and scrolling are a separate relative pointer device,
+    // with buttons so it is recognized as a mouse
+    let mut pointer_keys = AttributeSet::<Key>::new();
+    pointer_keys.insert(Key::BTN_LEFT);
+    pointer_keys.insert(Key::BTN_RIGHT);
+    pointer_keys.insert(Key::BTN_MIDDLE);
+    let mut pointer_axes = AttributeSet::<RelativeAxisType>::new();
+    pointer_axes.insert(RelativeAxisType::REL_X);
+    pointer_axes.insert(RelativeAxisType::REL_Y);
+    pointer_axes.insert(RelativeAxisType::REL_WHEEL);
+    pointer_axes.insert(RelativeAxisType::REL_HWHEEL);
+    pointer_axes.insert(RelativeAxisType::REL_WHEEL_HI_RES);
+    pointer_axes.insert(RelativeAxisType::REL_HWHEEL_HI_RES);
+    let pointer_out: BoxedSink = if dry_run {
+        Box::new(PrintSink::new("pointer"))
+    } else {
+        Box::new(
+            VirtualDeviceBuilder::new()
+                .expect("pointer new")
+                .name("padmixer pointer")
+                .with_keys(&pointer_keys)
+                .expect("pointer with_keys")
+                .with_relative_axes(&pointer_axes)
+                .expect("pointer with_relative_axes")
+                .build()
+                .expect("pointer build"),
+        )
+    };
+
+    let outputs: SharedOutputs = Arc::new(Mutex::new(Outputs {
+        keyboard: KeyTracker::new(vd),
+        pointer: pointer_out,
+        // virtual gamepads are made per pad, for pads whose profile asks for one
+        gamepads: HashMap::new(),
+        paused: false,
+    }));
+    // keys still held when padmixer goes away would stay stuck on the desktop
+    release::install_panic_hook(outputs.clone());
+    let arc_store = Arc::new(Mutex::new(ValueStore::new()));
 
     // personal logic loop that waits for pad input
     let mut runtime_store_binding = arc_store.clone();
+    let runtime_outputs = outputs.clone();
     runtime().spawn(async move {
         println!("spawned input thread...");
-        let mut gilrs = GilrsBuilder::new().set_update_state(false).build().unwrap();
-        let mut current_gamepad = None;
+        let _release = ReleaseOnDrop(runtime_outputs.clone());
+        // a recorded session stands in for the pad when one is given
+        let mut replay = std::env::var_os("PADMIXER_REPLAY").and_then(|path| {
+            match ReplaySource::load(Path::new(&path)) {
+                Ok(replay) => Some(replay),
+                Err(e) => {
+                    println!("unable to load the replay: {}", e);
+                    None
+                }
+            }
+        });
+        let replaying = replay.is_some();
+        // pad input can be saved for replays. a session holds one pad, the first to send input.
+        let mut recorder = std::env::var_os("PADMIXER_RECORD").and_then(|path| {
+            match SessionRecorder::create(Path::new(&path)) {
+                Ok(recorder) => Some(recorder),
+                Err(e) => {
+                    println!("unable to record the session: {}", e);
+                    None
+                }
+            }
+        });
+        let mut recorded_pad = None;
+        let mut profiles = Profiles::new(default_profile, rules);
+        let mut pads: HashMap<PadId, Pad> = HashMap::new();
+        let mut toasts = Toasts::default();
+        let mut gilrs: Option<GilrsSource> = None;
+        // the pad read through evdev, on the evdev backend or when it is grabbed
+        let mut evdev_pad: Option<EvdevPad> = None;
+        let mut next_open = Instant::now();
+        let mut open_failed = false;
+        let mut next_tick = Instant::now() + FRAME_INTERVAL;
         loop {
-            // println!("polling input...");
-            while let Some(event) = gilrs.next_event_blocking(None) {
-                gilrs.update(&event);
-                current_gamepad = Some(event.id);
-                let mut bcs = bcs.write().unwrap();
-                match event.event {
-                    gilrs::EventType::ButtonPressed(button, _code) => {
-                        bcs.try_update_button(button_to_bcs(button), 1.0)
-                    }
-                    gilrs::EventType::ButtonRepeated(button, _code) => {
-                        bcs.try_update_button(button_to_bcs(button), 1.0)
+            // gilrs runs unless the pad is only read through evdev.
+            // the default profile picks how pads are read.
+            // virtual gamepads show up like any other pad, but are our own output.
+            let own = profiles.own_gamepads();
+            match profiles.default.input.backend {
+                Backend::Gilrs if !replaying && gilrs.is_none() => {
+                    gilrs = Some(GilrsSource::new(own.clone()).expect("gilrs new"));
+                }
+                // pads gilrs was reading go with it, and get their slot back
+                // if evdev opens them
+                Backend::Evdev if gilrs.is_some() => {
+                    gilrs = None;
+                    let evdev_id = evdev_pad.as_ref().map(EvdevPad::id);
+                    let gone: Vec<PadId> = pads
+                        .keys()
+                        .copied()
+                        .filter(|id| Some(*id) != evdev_id)
+                        .collect();
+                    let mut store = runtime_store_binding.lock().unwrap();
+                    let mut outputs = runtime_outputs.lock().unwrap();
+                    for id in gone {
+                        let mut pad = pads.remove(&id).unwrap();
+                        close_pad(&mut pad, &mut outputs, &mut store);
+                        profiles.remember(&pad);
                     }
-                    gilrs::EventType::ButtonReleased(button, _code) => {
-                        bcs.try_update_button(button_to_bcs(button), 0.0)
+                }
+                _ => (),
+            }
+            if let Some(gilrs) = gilrs.as_mut() {
+                gilrs.ignore(own.clone());
+            }
+
+            // open the pad through evdev when the profile reads it that way.
+            // a grab on the gilrs backend waits until gilrs has found the pad,
+            // which keeps its slot.
+            let input = &profiles.default.input;
+            let wants_evdev = input.backend == Backend::Evdev || input.grab;
+            if !replaying && wants_evdev && evdev_pad.is_none() && Instant::now() >= next_open {
+                let opened = match input.backend {
+                    Backend::Evdev => Some(EvdevPad::open(PadId(0), input.grab, |path, device| {
+                        match input.device.as_deref() {
+                            Some(wanted) => {
+                                path == Path::new(wanted) || device.name() == Some(wanted)
+                            }
+                            // anything but our own virtual gamepads
+                            None => !own.iter().any(|own| device.name() == Some(own.as_str())),
+                        }
+                    })),
+                    Backend::Gilrs => {
+                        gilrs
+                            .as_ref()
+                            .and_then(GilrsSource::current)
+                            .map(|gamepad| {
+                                EvdevPad::open(PadId(gamepad.id().into()), true, |_, device| {
+                                    is_gilrs_pad(
+                                        device,
+                                        gamepad.os_name(),
+                                        gamepad.vendor_id(),
+                                        gamepad.product_id(),
+                                    )
+                                })
+                            })
                     }
-                    gilrs::EventType::ButtonChanged(button, value, _code) => {
-                        bcs.try_update_button(button_to_bcs(button), value)
+                };
+                match opened {
+                    Some(Ok(pad)) => {
+                        println!("reading {} through evdev", pad.name());
+                        evdev_pad = Some(pad);
+                        open_failed = false;
                     }
-                    gilrs::EventType::AxisChanged(axis, value, _code) => {
-                        let store = runtime_store_binding.borrow_mut();
-                        let mut store = store.lock().unwrap();
-                        if axis == Axis::RightStickX {
-                            store.get("radial_x").replace(Box::new(value), &mut store);
-                            // println!("insert to radial_x in store: {}", value);
-                        } else if axis == Axis::RightStickY {
-                            store.get("radial_y").replace(Box::new(value), &mut store);
-                            // println!("insert to radial_y in store: {}", value);
+                    Some(Err(e)) => {
+                        // reported once, then looked for quietly
+                        if !open_failed {
+                            println!("unable to open the pad through evdev: {}", e);
+                            open_failed = true;
                         }
-                        bcs.try_update_analog(axis_to_bcs(axis), value);
+                        next_open = Instant::now() + REOPEN_INTERVAL;
+                    }
+                    None => (),
+                }
+            }
+
+            // wait for input, but wake up on a fixed-rate tick anyway so timed bindings can
+            // resolve and the pointer keeps moving. everything pending is drained before
+            // the frame is processed.
+            let timeout = next_tick.saturating_duration_since(Instant::now());
+            // gilrs doesn't see a grabbed pad, but keeps reading every other pad
+            let mut polls = Vec::new();
+            if let (Some(_), Some(gilrs)) = (evdev_pad.as_ref(), gilrs.as_mut()) {
+                loop {
+                    match poll_source(gilrs, Duration::ZERO) {
+                        (Poll::Idle, _) => break,
+                        poll => polls.push(poll),
                     }
-                    gilrs::EventType::Connected => (),
-                    gilrs::EventType::Disconnected => (),
-                    gilrs::EventType::Dropped => (),
-                    gilrs::EventType::ForceFeedbackEffectCompleted => (),
-                    _ => (),
                 }
             }
-            if current_gamepad.is_some() {
-                // here is the basic prototype of button remapping to keyboard.
-                // pad 'x' or 'y' (mappings vary) sends KEY_H.
-
-                fn process_bind(vd: &mut VirtualDevice, gp: &Gamepad, inp: Button, out: Key) {
-                    let st = gp.state();
-                    let but = st.button_data(Gamepad::button_code(gp, inp).unwrap());
-                    if but.is_some() {
-                        let but = but.unwrap();
-                        if but.is_pressed() {
-                            let ie = InputEvent::new(EventType::KEY, out.code(), 1);
-                            let res = vd.emit(&[ie]);
-                            if res.is_err() {
-                                println!("{:?}", res);
+            let source: Option<&mut dyn InputSource> = if let Some(replay) = replay.as_mut() {
+                Some(replay)
+            } else if let Some(pad) = evdev_pad.as_mut() {
+                Some(pad)
+            } else {
+                gilrs.as_mut().map(|gilrs| gilrs as &mut dyn InputSource)
+            };
+            match source {
+                Some(source) => polls.push(poll_source(source, timeout)),
+                None => thread::sleep(timeout),
+            }
+            let mut input_time = None;
+            for (poll, info) in polls {
+                match poll {
+                    Poll::Connected(id) if !pads.contains_key(&id) => {
+                        // a pad that was here before gets its profile and slot back
+                        let info = info.unwrap_or_default();
+                        let (path, slot) = profiles.pick(&info);
+                        let slot = pads::free_slot(pads.values(), slot);
+                        let profile = profiles.get(path.as_deref()).clone();
+                        check_keys(&profile, &keyset);
+                        println!(
+                            "pad {}: {}, uuid {}, {}",
+                            slot + 1,
+                            info.name,
+                            pads::uuid_string(&info.uuid),
+                            match &path {
+                                Some(path) => format!("profile {}", path.display()),
+                                None => "default profile".to_string(),
                             }
-                        } else {
-                            let ie = InputEvent::new(EventType::KEY, out.code(), 0);
-                            let res = vd.emit(&[ie]);
-                            if res.is_err() {
-                                println!("{:?}", res);
+                        );
+                        let mut store = runtime_store_binding.lock().unwrap();
+                        let mut outputs = runtime_outputs.lock().unwrap();
+                        if let Some(gamepad) = virtual_gamepad(profile.gamepad.as_ref(), dry_run) {
+                            outputs.gamepads.insert(slot, gamepad);
+                        }
+                        let pad = Pad {
+                            info,
+                            mapper: Mapper::new(slot, profile, &mut store),
+                            profile: path,
+                            state: BasicControllerState::default(),
+                        };
+                        toasts.show(
+                            slot,
+                            format!("pad {} connected", slot + 1),
+                            Instant::now(),
+                            &mut store,
+                        );
+                        pads.insert(id, pad);
+                    }
+                    Poll::Connected(_) => (),
+                    Poll::Update(update) => {
+                        if *recorded_pad.get_or_insert(update.pad) == update.pad {
+                            if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(&update)) {
+                                println!("stopped recording the session: {}", e);
+                                recorder = None;
                             }
                         }
+                        if let Some(pad) = pads.get_mut(&update.pad) {
+                            pad.state = update.state;
+                        }
+                        input_time = Some(update.time);
+                    }
+                    Poll::Idle => (),
+                    Poll::Gone(id) => {
+                        if replay.take().is_some() {
+                            println!("replay finished");
+                        } else if let Some(pad) = evdev_pad.take_if(|pad| pad.id() == id) {
+                            println!("lost {}", pad.name());
+                        }
+                        // nothing stays held on a pad that is gone
+                        if let Some(mut pad) = pads.remove(&id) {
+                            let slot = pad.mapper.pad();
+                            println!("pad {} disconnected", slot + 1);
+                            let mut store = runtime_store_binding.lock().unwrap();
+                            close_pad(&mut pad, &mut runtime_outputs.lock().unwrap(), &mut store);
+                            toasts.show(
+                                slot,
+                                format!("pad {} disconnected", slot + 1),
+                                Instant::now(),
+                                &mut store,
+                            );
+                            profiles.remember(&pad);
+                        }
                     }
                 }
+            }
 
-                let gp = gilrs.gamepad(current_gamepad.unwrap());
-                process_bind(&mut vd, &gp, Button::Start, Key::KEY_M);
-                process_bind(&mut vd, &gp, Button::Select, Key::KEY_ESC);
-
-                process_bind(&mut vd, &gp, Button::North, Key::KEY_H);
-                process_bind(&mut vd, &gp, Button::South, Key::KEY_SPACE);
-                process_bind(&mut vd, &gp, Button::East, Key::KEY_LEFTALT);
-                process_bind(&mut vd, &gp, Button::West, Key::KEY_1);
-                process_bind(&mut vd, &gp, Button::RightTrigger, Key::KEY_LEFTCTRL);
-
-                let bcs = bcs.read().unwrap();
-                let r_lever = bcs
-                    .analog_state_by_type(types::CommonAnalog::RightLever)
-                    .value;
-                if r_lever > 0.0 {
-                    let ie = InputEvent::new(EventType::KEY, Key::KEY_LEFTSHIFT.code(), 1);
-                    let res = vd.emit(&[ie]);
-                    if res.is_err() {
-                        println!("{:?}", res);
+            // swap in reloaded profiles, on every pad using them
+            if let Some(next) = reloads.as_ref().and_then(|rx| rx.try_recv().ok()) {
+                check_keys(&next, &keyset);
+                let input_changed = next.input != profiles.default.input;
+                profiles.default = next;
+                let mut outputs = runtime_outputs.lock().unwrap();
+                for pad in pads.values_mut().filter(|pad| pad.profile.is_none()) {
+                    load_profile(pad, profiles.default.clone(), &mut outputs, dry_run);
+                }
+                if input_changed && !replaying {
+                    // the pad read through evdev starts over, and keeps its slot when it is back
+                    if let Some(mut pad) = evdev_pad.take().and_then(|pad| pads.remove(&pad.id())) {
+                        close_pad(
+                            &mut pad,
+                            &mut outputs,
+                            &mut runtime_store_binding.lock().unwrap(),
+                        );
+                        profiles.remember(&pad);
                     }
-                } else {
-                    let ie = InputEvent::new(EventType::KEY, Key::KEY_LEFTSHIFT.code(), 0);
-                    let res = vd.emit(&[ie]);
-                    if res.is_err() {
-                        println!("{:?}", res);
+                    next_open = Instant::now();
+                    open_failed = false;
+                }
+            }
+            for (path, next) in profiles.reloaded() {
+                check_keys(&next, &keyset);
+                let mut outputs = runtime_outputs.lock().unwrap();
+                for pad in pads.values_mut() {
+                    if pad.profile.as_ref() == Some(&path) {
+                        load_profile(pad, next.clone(), &mut outputs, dry_run);
                     }
                 }
+            }
 
-                // here we do the keys on the radial menu
-                let rs_x = bcs
-                    .analog_state_by_type(types::CommonAnalog::RightStickX)
-                    .value;
-                let rs_y = bcs
-                    .analog_state_by_type(types::CommonAnalog::RightStickY)
-                    .value;
-
-                if (f32::abs(rs_x) + f32::abs(rs_y)) > 0.5 {
-                    // calculate nearest coordinate in keys mapping
-                    // println!("are we pressing a key with the radial menu yet");
-                    let mut nearest = Key::KEY_UNKNOWN;
-                    let mut nearest_distance = 4.0;
-                    for key in keys.iter() {
-                        let co = key.2;
-                        let distance = f32::abs(f32::sqrt(
-                            f32::powf(co.0 - rs_x, 2.0) + f32::powf(co.1 - rs_y, 2.0),
-                        ));
-                        if distance < nearest_distance {
-                            // println!("co {:?} dist {} {}", co, distance, key.0);
-                            nearest_distance = distance;
-                            nearest = key.1;
-                        }
-                    }
-                    if nearest != Key::KEY_UNKNOWN {
-                        // println!("well, it is something");
-                        // release every key in the binding except shift
-                        for key in keys {
-                            if key.1 == Key::KEY_LEFTSHIFT {
-                                continue;
-                            }
-                            let ie = InputEvent::new(EventType::KEY, key.1.code(), 0);
-                            let res = vd.emit(&[ie]);
-                            if res.is_err() {
-                                println!("{:?}", res);
-                            }
-                        }
+            // frames run at the time their input was read
+            let now = input_time.unwrap_or_else(Instant::now);
+            let tick = now >= next_tick;
+            if tick {
+                // a late tick starts over from now instead of catching up
+                next_tick += FRAME_INTERVAL;
+                if next_tick <= now {
+                    next_tick = now + FRAME_INTERVAL;
+                }
+            }
 
-                        let ie = InputEvent::new(EventType::KEY, nearest.code(), 1);
-                        let res = vd.emit(&[ie]);
-                        if res.is_err() {
-                            println!("{:?}", res);
-                        }
-                    }
-                } else {
-                    for key in keys {
-                        let ie = InputEvent::new(EventType::KEY, key.1.code(), 0);
-                        let res = vd.emit(&[ie]);
-                        if res.is_err() {
-                            println!("{:?}", res);
-                        }
+            if !toasts.is_empty() {
+                toasts.expire(Instant::now(), &mut runtime_store_binding.lock().unwrap());
+            }
+
+            // frames are processed for every connected pad
+            if !pads.is_empty() {
+                let store = runtime_store_binding.borrow_mut();
+                let mut store = store.lock().unwrap();
+                let mut outputs = runtime_outputs.lock().unwrap();
+                if !release::exiting() {
+                    for pad in pads.values_mut() {
+                        pad.mapper
+                            .frame(&pad.state, now, tick, &mut outputs, &mut store);
                     }
                 }
             }
@@ -252,7 +474,19 @@ fn main() -> glib::ExitCode {
     });
 
     let app = Application::builder().application_id(APP_ID).build();
-    app.connect_startup(|_| {
+    let startup_outputs = outputs.clone();
+    app.connect_startup(move |app| {
+        // ctrl-c and a plain kill shut down like closing the app, releasing everything
+        for signal in [SIGINT, SIGTERM] {
+            let outputs = startup_outputs.clone();
+            let app = app.clone();
+            glib::unix_signal_add_local(signal, move || {
+                release_for_exit(&outputs);
+                app.quit();
+                glib::ControlFlow::Break
+            });
+        }
+
         // load gtk css. using this style to hide window backdrop
         //
         // window {
@@ -268,72 +502,288 @@ fn main() -> glib::ExitCode {
         );
     });
 
+    app.connect_shutdown(move |_| release_for_exit(&outputs));
+
     app.connect_activate(move |app| {
-        // window surface
-        let window = gtk::ApplicationWindow::new(app);
-        let window_native = window.native().unwrap();
-        window.set_title(Some("padmixer (in-development build)"));
-        window.init_layer_shell();
-        window.set_layer(Layer::Overlay);
-        window.set_size_request(380, 380);
-        window.set_margin(Edge::Bottom, 200);
-        window.set_margin(Edge::Right, 200);
-        let anchors = [
-            (Edge::Left, false),
-            (Edge::Top, false),
-            (Edge::Right, true),
-            (Edge::Bottom, true),
-        ];
-        for (anchor, state) in anchors {
-            window.set_anchor(anchor, state);
+        // each pad slot gets its overlays once a pad first takes it. a slot a pad
+        // left keeps them, empty, for the next pad to take it.
+        let app = app.clone();
+        let store = arc_store.clone();
+        let mut slots = 0;
+        glib::timeout_add_local(OVERLAY_INTERVAL, move || {
+            while store.lock().unwrap().map.contains_key(&status_key(slots)) {
+                for source in RadialSource::ALL {
+                    radial_window(&app, store.clone(), slots, source);
+                }
+                slots += 1;
+            }
+            glib::ControlFlow::Continue
+        });
+    });
+
+    app.run()
+}
+
+// one poll, with the pad's info when it just connected
+fn poll_source(source: &mut dyn InputSource, timeout: Duration) -> (Poll, Option<PadInfo>) {
+    let poll = source.poll(timeout);
+    let info = match &poll {
+        Poll::Connected(pad) => Some(source.info(*pad)),
+        _ => None,
+    };
+    (poll, info)
+}
+
+fn golden_check(golden: &Path) -> glib::ExitCode {
+    let Some(session) = std::env::var_os("PADMIXER_REPLAY") else {
+        println!("PADMIXER_GOLDEN needs a session in PADMIXER_REPLAY");
+        return glib::ExitCode::FAILURE;
+    };
+    let profile = profile::load(
+        RES.get_file("profile.toml")
+            .unwrap()
+            .contents_utf8()
+            .unwrap(),
+    );
+    match session::check(profile, Path::new(&session), golden) {
+        Ok(true) => glib::ExitCode::SUCCESS,
+        Ok(false) => glib::ExitCode::FAILURE,
+        Err(e) => {
+            println!("{}", e);
+            glib::ExitCode::FAILURE
+        }
+    }
+}
+
+fn check_keys(profile: &Profile, keyset: &AttributeSet<Key>) {
+    for key in profile.keys() {
+        if !keyset.contains(key) {
+            println!("{:?} is not registered on the virtual keyboard", key);
         }
+    }
+}
 
-        let radial = RadialMenu::default();
-        radial.set_labels(&*keys_string);
-        let rxc = radial_x.clone();
-        let ryc = radial_y.clone();
-        let store = arc_store.clone();
-        radial.add_tick_callback(move |wdg, _clk| {
-            // .queue_render() is automatic for GLArea.
-            let store = store.lock().unwrap();
-
-            let mut x = 0.0;
-            let x_value = rxc.lock().unwrap();
-            let x_opt = x_value.load(&store).as_any().downcast_ref::<f32>();
-            if let Some(new_x) = x_opt {
-                x = *new_x;
-            } else {
-                // println!("x might be nothing");
+// swaps a pad over to another profile, and its virtual gamepad with it
+fn load_profile(pad: &mut Pad, next: Profile, outputs: &mut Outputs<BoxedSink>, dry_run: bool) {
+    let slot = pad.mapper.pad();
+    let gamepad_changed = next.gamepad.as_ref().map(|g| &g.name)
+        != pad.mapper.profile().gamepad.as_ref().map(|g| &g.name);
+    pad.mapper.load(next, outputs);
+    if gamepad_changed {
+        outputs.gamepads.remove(&slot);
+        let config = pad.mapper.profile().gamepad.as_ref();
+        if let Some(gamepad) = virtual_gamepad(config, dry_run) {
+            outputs.gamepads.insert(slot, gamepad);
+        }
+    }
+}
+
+// lets go of everything a pad holds, empties its overlays and removes its virtual gamepad
+fn close_pad(pad: &mut Pad, outputs: &mut Outputs<BoxedSink>, store: &mut ValueStore) {
+    pad.mapper.close(outputs, store);
+    outputs.gamepads.remove(&pad.mapper.pad());
+}
+
+fn virtual_gamepad(
+    config: Option<&profile::Gamepad>,
+    dry_run: bool,
+) -> Option<(BoxedSink, GamepadMirror)> {
+    let config = config?;
+    if dry_run {
+        return Some((
+            Box::new(PrintSink::new("gamepad")),
+            GamepadMirror::default(),
+        ));
+    }
+    match gamepad::device(&config.name) {
+        Ok(device) => Some((Box::new(device), GamepadMirror::default())),
+        Err(e) => {
+            println!("unable to create the virtual gamepad: {}", e);
+            None
+        }
+    }
+}
+
+// an overlay window for one radial source of the pad in slot `pad`. it draws
+// nothing while no layer gives the source a radial menu, except that the right
+// stick's window also shows the pad's status and toasts.
+fn radial_window(
+    app: &Application,
+    store: Arc<Mutex<ValueStore>>,
+    pad: usize,
+    source: RadialSource,
+) {
+    // window surface
+    let window = gtk::ApplicationWindow::new(app);
+    let window_native = window.native().unwrap();
+    window.set_title(Some("padmixer (in-development build)"));
+    window.init_layer_shell();
+    window.set_layer(Layer::Overlay);
+    let overlay = {
+        let store = store.lock().unwrap();
+        let overlay_value = store.get(&source.store_key(pad, "overlay"));
+        let overlay_opt = overlay_value
+            .load(&store)
+            .as_any()
+            .downcast_ref::<Overlay>();
+        overlay_opt.cloned().unwrap_or(source.default_overlay())
+    };
+    place_window(&window, &overlay, pad);
+
+    let radial = RadialMenu::default();
+    radial.set_theme(overlay.theme);
+    let labels = RefCell::new(String::new());
+    let current_layout = RefCell::new(RadialLayout::default());
+    let breadcrumbs = RefCell::new(String::new());
+    let completions = RefCell::new(String::new());
+    let current_overlay = RefCell::new(overlay);
+    let status = RefCell::new(String::new());
+    let toast = RefCell::new(String::new());
+    let placed = window.clone();
+    radial.add_tick_callback(move |wdg, _clk| {
+        // .queue_render() is automatic for GLArea.
+        let store = store.lock().unwrap();
+        let value = |name: &str| store.get(&source.store_key(pad, name));
+
+        let mut x = 0.0;
+        let x_value = value("x");
+        let x_opt = x_value.load(&store).as_any().downcast_ref::<f32>();
+        if let Some(new_x) = x_opt {
+            x = *new_x;
+        }
+
+        let mut y = 0.0;
+        let y_value = value("y");
+        let y_opt = y_value.load(&store).as_any().downcast_ref::<f32>();
+        if let Some(new_y) = y_opt {
+            y = *new_y;
+        }
+
+        // labels change with the profile, the active layer and submenus, and
+        // always along with the sectors they sit in
+        let labels_value = value("labels");
+        let labels_opt = labels_value.load(&store).as_any().downcast_ref::<String>();
+        let layout_value = value("layout");
+        let layout_opt = layout_value
+            .load(&store)
+            .as_any()
+            .downcast_ref::<RadialLayout>();
+        if let (Some(new_labels), Some(new_layout)) = (labels_opt, layout_opt) {
+            if *new_labels != *labels.borrow() || *new_layout != *current_layout.borrow() {
+                labels.replace(new_labels.clone());
+                current_layout.replace(new_layout.clone());
+                wdg.set_ring(new_labels.as_str(), new_layout.clone());
             }
+        }
 
-            let mut y = 0.0;
-            let y_value = ryc.lock().unwrap();
-            let y_opt = y_value.load(&store).as_any().downcast_ref::<f32>();
-            if let Some(new_y) = y_opt {
-                y = *new_y;
-            } else {
-                // println!("y might be nothing");
+        // sector the input loop has settled on, -1 for none
+        let selected_value = value("selected");
+        let selected_opt = selected_value.load(&store).as_any().downcast_ref::<i32>();
+        if let Some(selected) = selected_opt {
+            wdg.set_selected(*selected);
+        }
+
+        // path of opened submenus, empty on the top ring
+        let breadcrumbs_value = value("breadcrumbs");
+        let breadcrumbs_opt = breadcrumbs_value
+            .load(&store)
+            .as_any()
+            .downcast_ref::<String>();
+        if let Some(new_breadcrumbs) = breadcrumbs_opt {
+            if *new_breadcrumbs != *breadcrumbs.borrow() {
+                breadcrumbs.replace(new_breadcrumbs.clone());
+                wdg.set_breadcrumbs(new_breadcrumbs.as_str());
             }
+        }
 
-            wdg.set_x(x);
-            wdg.set_y(y);
+        // word completions, one per line
+        let completions_value = value("completions");
+        let completions_opt = completions_value
+            .load(&store)
+            .as_any()
+            .downcast_ref::<String>();
+        if let Some(new_completions) = completions_opt {
+            if *new_completions != *completions.borrow() {
+                completions.replace(new_completions.clone());
+                wdg.set_completions(new_completions.as_str());
+            }
+        }
+        let completion_value = value("completion");
+        let completion_opt = completion_value.load(&store).as_any().downcast_ref::<i32>();
+        if let Some(completion) = completion_opt {
+            wdg.set_completion(*completion);
+        }
 
-            glib::ControlFlow::Continue
-        });
+        // "paused" while the kill switch holds all output, and toasts like the pad
+        // connecting. they are the pad's, not the source's, so only the right stick's
+        // overlay shows them.
+        if source == RadialSource::RightStick {
+            let status_value = store.get(&status_key(pad));
+            let status_opt = status_value.load(&store).as_any().downcast_ref::<String>();
+            if let Some(new_status) = status_opt {
+                if *new_status != *status.borrow() {
+                    status.replace(new_status.clone());
+                    wdg.set_status(new_status.as_str());
+                }
+            }
+            let toast_value = store.get(&toast_key(pad));
+            let toast_opt = toast_value.load(&store).as_any().downcast_ref::<String>();
+            if let Some(new_toast) = toast_opt {
+                if *new_toast != *toast.borrow() {
+                    toast.replace(new_toast.clone());
+                    wdg.set_toast(new_toast.as_str());
+                }
+            }
+        }
 
-        window.set_child(Some(&radial));
-        window.present();
-
-        // now that window is presented, nullify its input region
-        let surface = window_native.surface();
-        if surface.is_some() {
-            let surface = surface.unwrap();
-            let input_region = gtk::cairo::Region::create();
-            surface.set_input_region(&input_region);
-        } else {
-            println!("unable to disallow input region due to lack of surface on window");
+        // position and colors can change with the profile
+        let overlay_value = value("overlay");
+        let overlay_opt = overlay_value
+            .load(&store)
+            .as_any()
+            .downcast_ref::<Overlay>();
+        if let Some(new_overlay) = overlay_opt {
+            if *new_overlay != *current_overlay.borrow() {
+                current_overlay.replace(new_overlay.clone());
+                place_window(&placed, new_overlay, pad);
+                wdg.set_theme(new_overlay.theme);
+            }
         }
+
+        wdg.set_x(x);
+        wdg.set_y(y);
+
+        glib::ControlFlow::Continue
     });
 
-    app.run()
+    window.set_child(Some(&radial));
+    window.present();
+
+    // now that window is presented, nullify its input region
+    let surface = window_native.surface();
+    if let Some(surface) = surface {
+        let input_region = gtk::cairo::Region::create();
+        surface.set_input_region(&input_region);
+    } else {
+        println!("unable to disallow input region due to lack of surface on window");
+    }
+}
+
+// anchor the overlay to its screen corner. overlays of further pads move along
+// the screen edge by their size, so pads on the same profile don't overlap.
+fn place_window(window: &gtk::ApplicationWindow, overlay: &Overlay, pad: usize) {
+    let (horizontal, vertical) = match overlay.anchor {
+        Anchor::TopLeft => (Edge::Left, Edge::Top),
+        Anchor::TopRight => (Edge::Right, Edge::Top),
+        Anchor::BottomLeft => (Edge::Left, Edge::Bottom),
+        Anchor::BottomRight => (Edge::Right, Edge::Bottom),
+    };
+    window.set_size_request(overlay.size, overlay.size);
+    for edge in [Edge::Left, Edge::Top, Edge::Right, Edge::Bottom] {
+        let anchored = edge == horizontal || edge == vertical;
+        window.set_anchor(edge, anchored);
+        window.set_margin(edge, 0);
+    }
+    window.set_margin(horizontal, overlay.margin[0] + pad as i32 * overlay.size);
+    window.set_margin(vertical, overlay.margin[1]);
 }
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::analog::AnalogStage;
+use crate::binding::BindingEngine;
+use crate::frames::Frames;
+use crate::gamepad::GamepadMirror;
+use crate::kill_switch::{KillSwitch, Toggle};
+use crate::output::{KeyTracker, OutputSink, Recorder};
+use crate::pointer::{PointerDriver, ScrollDriver};
+use crate::profile::Profile;
+use crate::radial::{RadialDriver, RadialSource};
+use crate::types::{BasicControllerState, ValueStore};
+
+// how often the pointer and the wheels move, and timed bindings are checked
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(10);
+
+// ValueStore key for the status line every overlay of the pad in `pad`'s slot shows
+pub fn status_key(pad: usize) -> String {
+    format!("pad{}_status", pad)
+}
+
+// ValueStore key for a short message on the overlays of the pad in `pad`'s slot
+pub fn toast_key(pad: usize) -> String {
+    format!("pad{}_toast", pad)
+}
+
+// where a frame's output goes. the keyboard and pointer are shared by every pad.
+pub struct Outputs<S: OutputSink> {
+    // only real key transitions reach the keyboard, one batch per input frame.
+    // a key two pads hold stays down until both let go.
+    pub keyboard: KeyTracker<S>,
+    // the stick-driven cursor and scrolling
+    pub pointer: S,
+    // a virtual gamepad per pad slot, for pads whose profile asks for one
+    pub gamepads: HashMap<usize, (S, GamepadMirror)>,
+    // set by any pad's kill switch, and no pad sends anything while it is
+    pub paused: bool,
+}
+
+impl<S: OutputSink> Outputs<S> {
+    // lets go of every key and puts the virtual gamepads at rest, without going
+    // through the bindings
+    pub fn release_all(&mut self) {
+        self.keyboard.release_all();
+        self.keyboard.flush();
+        for (device, mirror) in self.gamepads.values_mut() {
+            mirror.release(device);
+        }
+    }
+}
+
+// turns one pad's state into output under one profile, one frame at a time.
+// the live input loop and replays both go through here.
+pub struct Mapper {
+    // slot of the pad, for its overlays and its virtual gamepad
+    pad: usize,
+    profile: Profile,
+    analogs: AnalogStage,
+    engine: BindingEngine,
+    radials: Vec<RadialDriver>,
+    pointer: PointerDriver,
+    scroll: ScrollDriver,
+    kill_switch: KillSwitch,
+    // whether this pad has followed the shared pause yet
+    paused: bool,
+    // the raw pad frame by frame, with how long each button is down
+    frames: Frames,
+    // keys this pad holds, passed on to the shared keyboard once per frame
+    keys: KeyTracker<Recorder>,
+}
+
+impl Mapper {
+    pub fn new(pad: usize, profile: Profile, store: &mut ValueStore) -> Self {
+        // every stick and the d-pad can drive a radial menu with its own overlay
+        let radials = RadialSource::ALL
+            .iter()
+            .map(|source| RadialDriver::new(*source, pad, store))
+            .collect();
+        let mut mapper = Self {
+            pad,
+            analogs: AnalogStage::new(&profile),
+            engine: BindingEngine::new(&profile),
+            profile,
+            radials,
+            pointer: PointerDriver::default(),
+            scroll: ScrollDriver::default(),
+            kill_switch: KillSwitch::default(),
+            paused: false,
+            frames: Frames::default(),
+            keys: KeyTracker::new(Recorder::default()),
+        };
+        // shown by every overlay of the pad, empty while running.
+        // the status goes last, overlays are made once it is there.
+        store.insert(&toast_key(pad), String::new());
+        store.insert(&status_key(pad), String::new());
+        for radial in mapper.radials.iter_mut() {
+            radial.sync(
+                mapper.engine.radial(radial.source()),
+                mapper.profile.thresholds.radial,
+                &mut mapper.keys,
+            );
+            radial.publish(store);
+        }
+        mapper
+    }
+
+    pub fn pad(&self) -> usize {
+        self.pad
+    }
+
+    pub fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    // lets go of everything this pad holds and starts the bindings over.
+    // keys other pads hold stay down.
+    pub fn release_all<S: OutputSink>(&mut self, out: &mut Outputs<S>) {
+        self.engine.release_all(&mut self.keys);
+        for radial in self.radials.iter_mut() {
+            radial.reset();
+        }
+        self.keys.release_all();
+        self.flush(out);
+        if let Some((device, mirror)) = out.gamepads.get_mut(&self.pad) {
+            mirror.release(device);
+        }
+    }
+
+    // hands this frame's key transitions to the shared keyboard
+    fn flush<S: OutputSink>(&mut self, out: &mut Outputs<S>) {
+        self.keys.flush();
+        for frame in self.keys.sink().frames.drain(..) {
+            out.keyboard.send(&frame);
+        }
+        out.keyboard.flush();
+    }
+
+    // lets go of everything and empties the pad's overlays, for a pad that is gone
+    pub fn close<S: OutputSink>(&mut self, out: &mut Outputs<S>, store: &mut ValueStore) {
+        self.release_all(out);
+        for radial in self.radials.iter_mut() {
+            radial.publish(store);
+        }
+        store
+            .get(&status_key(self.pad))
+            .replace(Box::new(String::new()), store);
+    }
+
+    // swaps in another profile, releasing the old profile's keys first
+    pub fn load<S: OutputSink>(&mut self, profile: Profile, out: &mut Outputs<S>) {
+        self.release_all(out);
+        self.analogs = AnalogStage::new(&profile);
+        self.engine = BindingEngine::new(&profile);
+        self.profile = profile;
+    }
+
+    // one frame of the raw pad state at `now`. the pointer and the wheels only
+    // move on a `tick`, by a whole frame, so their speed doesn't depend on how
+    // many events arrive.
+    pub fn frame<S: OutputSink>(
+        &mut self,
+        raw: &BasicControllerState,
+        now: Instant,
+        tick: bool,
+        out: &mut Outputs<S>,
+        store: &mut ValueStore,
+    ) {
+        // the raw pad is followed even while paused, so press times stay right
+        let diff = self.frames.push(*raw, now);
+
+        // the kill switch reads the raw pad before anything from the profile does,
+        // and pauses every pad
+        match self
+            .kill_switch
+            .update(&self.profile.kill_switch, &self.frames, out.paused)
+        {
+            Some(Toggle::Paused) => {
+                println!(
+                    "paused from pad {}, hold the kill switch again to resume",
+                    self.pad + 1
+                );
+                out.paused = true;
+                out.release_all();
+            }
+            Some(Toggle::Resumed) => {
+                println!("resumed from pad {}", self.pad + 1);
+                out.paused = false;
+            }
+            None => (),
+        }
+        // whichever pad paused, every pad lets go and shows it
+        if self.paused != out.paused {
+            self.paused = out.paused;
+            if self.paused {
+                self.release_all(out);
+            }
+            let status = match self.paused {
+                true => "paused",
+                false => "",
+            };
+            store
+                .get(&status_key(self.pad))
+                .replace(Box::new(status.to_string()), store);
+        }
+        if self.paused || self.kill_switch.holding() {
+            return;
+        }
+
+        // button and analog binds from the profile
+        // deadzones and curves apply before anything reads the sticks
+        let bcs = self.analogs.process(raw);
+        self.engine.update(&bcs, &diff, now, &mut self.keys);
+
+        if tick {
+            let (pointer, scroll) = (self.engine.pointer(), self.engine.scroll());
+            self.pointer
+                .tick(pointer, &bcs, FRAME_INTERVAL, &mut out.pointer);
+            self.scroll
+                .tick(scroll, &bcs, FRAME_INTERVAL, &mut out.pointer);
+        }
+
+        // each radial menu follows the active layers
+        for radial in self.radials.iter_mut() {
+            radial.sync(
+                self.engine.radial(radial.source()),
+                self.profile.thresholds.radial,
+                &mut self.keys,
+            );
+            radial.update(&bcs, now, &mut self.keys);
+            radial.publish(store);
+        }
+
+        // whatever the active layers leave alone goes to the virtual gamepad
+        if let (Some(config), Some((device, mirror))) = (
+            self.profile.gamepad.as_ref(),
+            out.gamepads.get_mut(&self.pad),
+        ) {
+            mirror.update(config, &self.engine.claims(), &bcs, device);
+        }
+
+        self.flush(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev::{InputEventKind, Key};
+
+    use super::*;
+    use crate::output::Recorder;
+    use crate::types::CommonButton;
+
+    const PROFILE: &str = "[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_A\" }\n\n[[bind]]\nbutton = \"FaceEast\"\naction = { key = \"KEY_B\" }\n";
+
+    // a mapper on pad 0 and everything it sends to
+    struct Rig {
+        mapper: Mapper,
+        out: Outputs<Recorder>,
+        store: ValueStore,
+        state: BasicControllerState,
+        start: Instant,
+    }
+
+    impl Rig {
+        fn new() -> Self {
+            let mut store = ValueStore::new();
+            Self {
+                mapper: Mapper::new(0, Profile::parse(PROFILE).unwrap(), &mut store),
+                out: Outputs {
+                    keyboard: KeyTracker::new(Recorder::default()),
+                    pointer: Recorder::default(),
+                    gamepads: HashMap::new(),
+                    paused: false,
+                },
+                store,
+                state: BasicControllerState::default(),
+                start: Instant::now(),
+            }
+        }
+
+        fn set(&mut self, buttons: &[CommonButton], value: f32, ms: u64) {
+            for button in buttons {
+                self.state.try_update_button(*button, value);
+            }
+            let now = self.start + Duration::from_millis(ms);
+            self.mapper
+                .frame(&self.state, now, false, &mut self.out, &mut self.store);
+        }
+
+        // holds A and B, and forgets the frames that pressed them
+        fn hold_keys(&mut self) {
+            self.set(&[CommonButton::FaceSouth, CommonButton::FaceEast], 1.0, 0);
+            assert_eq!(
+                self.keyboard(),
+                vec![vec![(Key::KEY_A, 1), (Key::KEY_B, 1)]]
+            );
+        }
+
+        // the status line the overlays of the pad in `pad`'s slot show
+        fn status(&self, pad: usize) -> String {
+            let status = self.store.get(&status_key(pad));
+            let status = status.load(&self.store).as_any().downcast_ref::<String>();
+            status.unwrap().clone()
+        }
+
+        // key events the keyboard got since the last call, frame by frame
+        fn keyboard(&mut self) -> Vec<Vec<(Key, i32)>> {
+            self.out
+                .keyboard
+                .sink()
+                .frames
+                .drain(..)
+                .map(|frame| {
+                    frame
+                        .iter()
+                        .filter_map(|event| match event.kind() {
+                            InputEventKind::Key(key) => Some((key, event.value())),
+                            _ => None,
+                        })
+                        .collect()
+                })
+                .collect()
+        }
+    }
+
+    const RELEASED: [(Key, i32); 2] = [(Key::KEY_A, 0), (Key::KEY_B, 0)];
+
+    #[test]
+    fn release_all_lets_go_of_every_held_key() {
+        let mut rig = Rig::new();
+        rig.hold_keys();
+        rig.out.release_all();
+        assert_eq!(rig.keyboard(), vec![RELEASED.to_vec()]);
+        // nothing is left to let go of
+        rig.out.release_all();
+        assert!(rig.keyboard().is_empty());
+    }
+
+    #[test]
+    fn closing_a_lost_pad_lets_go_of_its_keys() {
+        let mut rig = Rig::new();
+        rig.hold_keys();
+        rig.mapper.close(&mut rig.out, &mut rig.store);
+        assert_eq!(rig.keyboard(), vec![RELEASED.to_vec()]);
+    }
+
+    #[test]
+    fn pausing_lets_go_of_held_keys_and_sends_nothing_more() {
+        let mut rig = Rig::new();
+        rig.hold_keys();
+        let chord = [
+            CommonButton::Start,
+            CommonButton::Select,
+            CommonButton::Guide,
+        ];
+        rig.set(&chord, 1.0, 100);
+        rig.set(&[], 0.0, 2099);
+        assert!(rig.keyboard().is_empty());
+        rig.set(&[], 0.0, 2100);
+        assert_eq!(rig.keyboard(), vec![RELEASED.to_vec()]);
+        // bindings stay quiet while paused
+        rig.set(
+            &[CommonButton::FaceSouth, CommonButton::FaceEast],
+            0.0,
+            2200,
+        );
+        rig.set(&[CommonButton::FaceSouth], 1.0, 2300);
+        rig.set(&chord, 0.0, 2400);
+        assert!(rig.keyboard().is_empty());
+    }
+
+    #[test]
+    fn one_pad_pauses_and_resumes_every_pad() {
+        let mut rig = Rig::new();
+        rig.hold_keys();
+        // a second pad, holding B as well
+        let mut other = Mapper::new(1, Profile::parse(PROFILE).unwrap(), &mut rig.store);
+        let mut other_state = BasicControllerState::default();
+        let mut other_frame = |rig: &mut Rig, button, value, ms| {
+            other_state.try_update_button(button, value);
+            let now = rig.start + Duration::from_millis(ms);
+            other.frame(&other_state, now, false, &mut rig.out, &mut rig.store);
+        };
+        other_frame(&mut rig, CommonButton::FaceEast, 1.0, 0);
+        assert!(rig.keyboard().is_empty());
+
+        let chord = [
+            CommonButton::Start,
+            CommonButton::Select,
+            CommonButton::Guide,
+        ];
+        rig.set(&chord, 1.0, 100);
+        rig.set(&[], 0.0, 2100);
+        assert_eq!(rig.keyboard(), vec![RELEASED.to_vec()]);
+        // the other pad sends nothing either, and both overlays show the pause
+        other_frame(&mut rig, CommonButton::FaceEast, 0.0, 2200);
+        other_frame(&mut rig, CommonButton::FaceSouth, 1.0, 2300);
+        other_frame(&mut rig, CommonButton::FaceSouth, 0.0, 2400);
+        assert!(rig.keyboard().is_empty());
+        assert_eq!(rig.status(0), "paused");
+        assert_eq!(rig.status(1), "paused");
+
+        rig.set(&chord, 0.0, 2500);
+        rig.set(&chord, 1.0, 2600);
+        rig.set(&[], 0.0, 4600);
+        assert_eq!(rig.status(0), "");
+        other_frame(&mut rig, CommonButton::FaceSouth, 1.0, 4700);
+        assert_eq!(rig.keyboard(), vec![vec![(Key::KEY_A, 1)]]);
+        assert_eq!(rig.status(1), "");
+    }
+}
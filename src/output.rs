@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use evdev::uinput::VirtualDevice;
+use evdev::{EventType, InputEvent, InputEventKind, Key};
+
+// anything synthesized events can be written to.
+// each call is one input frame and ends with a SYN_REPORT on real devices.
+pub trait OutputSink {
+    fn send(&mut self, events: &[InputEvent]);
+}
+
+// a sink picked at startup, real device or not
+pub type BoxedSink = Box<dyn OutputSink + Send>;
+
+impl<S: OutputSink + ?Sized> OutputSink for Box<S> {
+    fn send(&mut self, events: &[InputEvent]) {
+        (**self).send(events);
+    }
+}
+
+impl<S: OutputSink + ?Sized> OutputSink for &mut S {
+    fn send(&mut self, events: &[InputEvent]) {
+        (**self).send(events);
+    }
+}
+
+impl OutputSink for VirtualDevice {
+    fn send(&mut self, events: &[InputEvent]) {
+        let res = self.emit(events);
+        if res.is_err() {
+            println!("{:?}", res);
+        }
+    }
+}
+
+// keeps every frame in memory, for replays and checking what bindings send
+#[derive(Default)]
+pub struct Recorder {
+    pub frames: Vec<Vec<InputEvent>>,
+}
+
+impl OutputSink for Recorder {
+    fn send(&mut self, events: &[InputEvent]) {
+        self.frames.push(events.to_vec());
+    }
+}
+
+// prints frames instead of sending them, for dry runs without /dev/uinput
+pub struct PrintSink {
+    name: String,
+}
+
+impl PrintSink {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl OutputSink for PrintSink {
+    fn send(&mut self, events: &[InputEvent]) {
+        let described = describe_frame(events);
+        if !described.is_empty() {
+            println!("{}: {}", self.name, described);
+        }
+    }
+}
+
+// a frame of events the way evdev names them, like "KEY_A down, REL_X 5"
+pub fn describe_frame(events: &[InputEvent]) -> String {
+    let described: Vec<String> = events.iter().filter_map(describe).collect();
+    described.join(", ")
+}
+
+fn describe(event: &InputEvent) -> Option<String> {
+    let value = event.value();
+    let described = match event.kind() {
+        InputEventKind::Key(key) => {
+            let state = match value {
+                0 => "up",
+                1 => "down",
+                _ => "repeat",
+            };
+            format!("{:?} {}", key, state)
+        }
+        InputEventKind::RelAxis(axis) => format!("{:?} {}", axis, value),
+        InputEventKind::AbsAxis(axis) => format!("{:?} {}", axis, value),
+        InputEventKind::Synchronization(_) => return None,
+        _ => format!("{:?} {} {}", event.event_type(), event.code(), value),
+    };
+    Some(described)
+}
+
+// remembers which keys the wrapped sink currently holds.
+// sent events are collected into a frame, keeping only real key transitions,
+// and `flush` writes the frame to the sink in one batch.
+// presses are counted, so a key shared by two bindings stays down until both let go.
+pub struct KeyTracker<S: OutputSink> {
+    sink: S,
+    held: HashMap<Key, u32>,
+    frame: Vec<InputEvent>,
+}
+
+impl<S: OutputSink> KeyTracker<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            held: HashMap::new(),
+            frame: Vec::new(),
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        let mut held: Vec<Key> = self.held.drain().map(|(key, _)| key).collect();
+        held.sort();
+        self.frame.extend(
+            held.into_iter()
+                .map(|key| InputEvent::new(EventType::KEY, key.code(), 0)),
+        );
+    }
+
+    pub fn sink(&mut self) -> &mut S {
+        &mut self.sink
+    }
+
+    pub fn flush(&mut self) {
+        if !self.frame.is_empty() {
+            self.sink.send(&self.frame);
+            self.frame.clear();
+        }
+    }
+}
+
+impl<S: OutputSink> OutputSink for KeyTracker<S> {
+    fn send(&mut self, events: &[InputEvent]) {
+        for event in events {
+            if let InputEventKind::Key(key) = event.kind() {
+                let changed = match event.value() {
+                    0 => match self.held.get_mut(&key) {
+                        Some(1) => self.held.remove(&key).is_some(),
+                        Some(count) => {
+                            *count -= 1;
+                            false
+                        }
+                        None => false,
+                    },
+                    1 => {
+                        let count = self.held.entry(key).or_insert(0);
+                        *count += 1;
+                        *count == 1
+                    }
+                    // autorepeat, only meaningful while held
+                    _ => self.held.contains_key(&key),
+                };
+                if !changed {
+                    continue;
+                }
+            }
+            self.frame.push(*event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use evdev::RelativeAxisType;
+
+    use super::*;
+
+    fn key(key: Key, value: i32) -> InputEvent {
+        InputEvent::new(EventType::KEY, key.code(), value)
+    }
+
+    // every frame the tracker sent so far, described
+    fn sent(keys: &mut KeyTracker<Recorder>) -> Vec<String> {
+        keys.sink()
+            .frames
+            .iter()
+            .map(|frame| describe_frame(frame))
+            .collect()
+    }
+
+    #[test]
+    fn a_key_two_binds_hold_stays_down_until_both_let_go() {
+        let mut keys = KeyTracker::new(Recorder::default());
+        keys.send(&[key(Key::KEY_A, 1)]);
+        keys.send(&[key(Key::KEY_A, 1)]);
+        keys.flush();
+        keys.send(&[key(Key::KEY_A, 0)]);
+        keys.flush();
+        assert_eq!(sent(&mut keys), ["KEY_A down"]);
+        keys.send(&[key(Key::KEY_A, 0)]);
+        keys.flush();
+        assert_eq!(sent(&mut keys), ["KEY_A down", "KEY_A up"]);
+        // releasing a key nobody holds, or repeating it, sends nothing
+        keys.send(&[key(Key::KEY_A, 0), key(Key::KEY_A, 2)]);
+        keys.flush();
+        assert_eq!(sent(&mut keys).len(), 2);
+    }
+
+    #[test]
+    fn a_frame_goes_out_in_one_batch() {
+        let mut keys = KeyTracker::new(Recorder::default());
+        let wheel = InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_WHEEL.0, 1);
+        keys.send(&[key(Key::KEY_LEFTCTRL, 1), key(Key::KEY_C, 1)]);
+        keys.send(&[wheel, key(Key::KEY_C, 0)]);
+        assert!(sent(&mut keys).is_empty());
+        keys.flush();
+        // nothing is left over for the next flush
+        keys.flush();
+        assert_eq!(
+            sent(&mut keys),
+            ["KEY_LEFTCTRL down, KEY_C down, REL_WHEEL 1, KEY_C up"]
+        );
+    }
+
+    #[test]
+    fn release_all_lets_go_of_every_held_key_in_order() {
+        let mut keys = KeyTracker::new(Recorder::default());
+        keys.send(&[key(Key::KEY_B, 1), key(Key::KEY_A, 1), key(Key::KEY_A, 1)]);
+        keys.flush();
+        keys.release_all();
+        keys.flush();
+        // and the binds still holding A have nothing left to release
+        keys.send(&[key(Key::KEY_A, 0)]);
+        keys.flush();
+        assert_eq!(
+            sent(&mut keys),
+            ["KEY_B down, KEY_A down", "KEY_A up, KEY_B up"]
+        );
+    }
+
+    #[test]
+    fn frames_are_described_the_way_evdev_names_them() {
+        let syn = InputEvent::new(EventType::SYNCHRONIZATION, 0, 0);
+        let frame = [
+            key(Key::KEY_A, 1),
+            key(Key::KEY_A, 0),
+            key(Key::KEY_A, 2),
+            InputEvent::new(EventType::RELATIVE, RelativeAxisType::REL_X.0, -5),
+            syn,
+        ];
+        assert_eq!(
+            describe_frame(&frame),
+            "KEY_A down, KEY_A up, KEY_A repeat, REL_X -5"
+        );
+        assert_eq!(describe_frame(&[syn]), "");
+    }
+}
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use async_channel::Receiver;
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::input::PadInfo;
+use crate::mapper::{toast_key, Mapper};
+use crate::profile::{self, config_dir, line_of, InputSettings, Profile, ProfileError};
+use crate::types::{BasicControllerState, ValueStore};
+
+pub const PADS_FILE: &str = "pads.toml";
+// how long a toast stays on the overlays
+const TOAST_DURATION: Duration = Duration::from_secs(3);
+
+// pads a rule matches use its profile instead of the default.
+// everything the rule gives has to match.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PadRule {
+    pub name: Option<String>,
+    pub uuid: Option<[u8; 16]>,
+    pub vendor: Option<u16>,
+    pub product: Option<u16>,
+    pub profile: PathBuf,
+}
+
+impl PadRule {
+    pub fn matches(&self, info: &PadInfo) -> bool {
+        self.name.as_ref().is_none_or(|name| *name == info.name)
+            && self.uuid.is_none_or(|uuid| uuid == info.uuid)
+            && self.vendor.is_none_or(|vendor| Some(vendor) == info.vendor)
+            && self
+                .product
+                .is_none_or(|product| Some(product) == info.product)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPads {
+    #[serde(default)]
+    pad: Vec<Spanned<RawPadRule>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPadRule {
+    name: Option<String>,
+    uuid: Option<Spanned<String>>,
+    vendor: Option<u16>,
+    product: Option<u16>,
+    profile: Spanned<String>,
+}
+
+pub fn parse(src: &str) -> Result<Vec<PadRule>, ProfileError> {
+    let raw: RawPads = toml::from_str(src).map_err(|e| ProfileError {
+        line: e.span().map(|span| line_of(src, span)),
+        message: e.message().to_string(),
+    })?;
+    let error = |span, message: &str| ProfileError {
+        line: Some(line_of(src, span)),
+        message: message.to_string(),
+    };
+
+    let mut rules = Vec::new();
+    for rule in raw.pad {
+        let span = rule.span();
+        let rule = rule.into_inner();
+        if rule.name.is_none()
+            && rule.uuid.is_none()
+            && rule.vendor.is_none()
+            && rule.product.is_none()
+        {
+            return Err(error(
+                span,
+                "pad needs a name, uuid, vendor or product to match",
+            ));
+        }
+        let uuid = match rule.uuid {
+            Some(uuid) => match parse_uuid(uuid.get_ref()) {
+                Some(parsed) => Some(parsed),
+                None => return Err(error(uuid.span(), "uuid is not 32 hex digits")),
+            },
+            None => None,
+        };
+        if rule.profile.get_ref().trim().is_empty() {
+            return Err(error(rule.profile.span(), "pad profile is empty"));
+        }
+        rules.push(PadRule {
+            name: rule.name,
+            uuid,
+            vendor: rule.vendor,
+            product: rule.product,
+            profile: PathBuf::from(rule.profile.into_inner()),
+        });
+    }
+    Ok(rules)
+}
+
+// 32 hex digits, dashes allowed anywhere
+fn parse_uuid(src: &str) -> Option<[u8; 16]> {
+    let hex: String = src.chars().filter(|ch| *ch != '-').collect();
+    if hex.len() != 32 {
+        return None;
+    }
+    let mut uuid = [0; 16];
+    for (i, byte) in uuid.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(uuid)
+}
+
+// the way pads.toml spells a uuid
+pub fn uuid_string(uuid: &[u8; 16]) -> String {
+    uuid.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// the rules in the config directory's pads.toml, with profile paths relative to
+// that directory. a missing or broken file gives no rules, so every pad uses the default.
+pub fn load() -> Vec<PadRule> {
+    let Some(dir) = config_dir() else {
+        return Vec::new();
+    };
+    let path = dir.join(PADS_FILE);
+    let src = match fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+    match parse(&src) {
+        Ok(rules) => rules
+            .into_iter()
+            .map(|rule| PadRule {
+                profile: dir.join(&rule.profile),
+                ..rule
+            })
+            .collect(),
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            Vec::new()
+        }
+    }
+}
+
+// the profile of the first rule matching the pad, none for the default
+pub fn assign<'a>(rules: &'a [PadRule], info: &PadInfo) -> Option<&'a Path> {
+    rules
+        .iter()
+        .find(|rule| rule.matches(info))
+        .map(|rule| rule.profile.as_path())
+}
+
+// the default profile, the profiles pads.toml assigns, and what each pad had
+pub struct Profiles {
+    pub default: Profile,
+    rules: Vec<PadRule>,
+    // loaded and watched once a pad needs them
+    assigned: HashMap<PathBuf, Profile>,
+    reloads: Vec<(PathBuf, Receiver<Profile>)>,
+    // profile and slot of pads that left, by uuid, for when they come back
+    left: HashMap<[u8; 16], (Option<PathBuf>, usize)>,
+}
+
+impl Profiles {
+    pub fn new(default: Profile, rules: Vec<PadRule>) -> Self {
+        Self {
+            default,
+            rules,
+            assigned: HashMap::new(),
+            reloads: Vec::new(),
+            left: HashMap::new(),
+        }
+    }
+
+    // the profile for a pad that connected, and the slot it had before if it was
+    // here already. an assigned profile that doesn't load leaves the pad on the default.
+    pub fn pick(&mut self, info: &PadInfo) -> (Option<PathBuf>, Option<usize>) {
+        if let Some((path, slot)) = self.left.get(&info.uuid) {
+            return (path.clone(), Some(*slot));
+        }
+        let path = assign(&self.rules, info).map(Path::to_path_buf);
+        let path = path.filter(|path| self.load(path));
+        (path, None)
+    }
+
+    fn load(&mut self, path: &Path) -> bool {
+        if self.assigned.contains_key(path) {
+            return true;
+        }
+        let Some(profile) = profile::load_file(path) else {
+            return false;
+        };
+        warn_ignored_input(path, &profile);
+        self.assigned.insert(path.to_path_buf(), profile);
+        self.reloads
+            .push((path.to_path_buf(), profile::watch(path.to_path_buf())));
+        true
+    }
+
+    // none for the default
+    pub fn get(&self, path: Option<&Path>) -> &Profile {
+        path.and_then(|path| self.assigned.get(path))
+            .unwrap_or(&self.default)
+    }
+
+    // a pad with no uuid to tell it apart, like a replay, is not remembered
+    pub fn remember(&mut self, pad: &Pad) {
+        if pad.info.uuid != [0; 16] {
+            let left = (pad.profile.clone(), pad.mapper.pad());
+            self.left.insert(pad.info.uuid, left);
+        }
+    }
+
+    // assigned profiles that changed on disk since the last call
+    pub fn reloaded(&mut self) -> Vec<(PathBuf, Profile)> {
+        let mut reloaded = Vec::new();
+        for (path, rx) in self.reloads.iter() {
+            while let Ok(next) = rx.try_recv() {
+                warn_ignored_input(path, &next);
+                self.assigned.insert(path.clone(), next.clone());
+                reloaded.push((path.clone(), next));
+            }
+        }
+        reloaded
+    }
+
+    // names of the virtual gamepads these profiles make, which input must skip
+    pub fn own_gamepads(&self) -> Vec<String> {
+        std::iter::once(&self.default)
+            .chain(self.assigned.values())
+            .filter_map(|profile| profile.gamepad.as_ref())
+            .map(|gamepad| gamepad.name.clone())
+            .collect()
+    }
+}
+
+// how pads are read is up to the default profile alone
+fn warn_ignored_input(path: &Path, profile: &Profile) {
+    if profile.input != InputSettings::default() {
+        println!(
+            "{}: [input] is only read from the default profile, ignoring it",
+            path.display()
+        );
+    }
+}
+
+// a connected pad and everything kept for it
+pub struct Pad {
+    pub info: PadInfo,
+    pub mapper: Mapper,
+    // the profile from pads.toml, none for the default
+    pub profile: Option<PathBuf>,
+    pub state: BasicControllerState,
+}
+
+// `wanted` if no pad is in it, otherwise the lowest slot no pad is in.
+// slots number the overlays and show as "pad 1" and up.
+pub fn free_slot<'a>(pads: impl Iterator<Item = &'a Pad> + Clone, wanted: Option<usize>) -> usize {
+    let free = |slot: &usize| pads.clone().all(|pad| pad.mapper.pad() != *slot);
+    wanted
+        .filter(free)
+        .unwrap_or_else(|| (0..).find(free).unwrap())
+}
+
+// short messages on the overlays of a pad slot, like the pad connecting
+#[derive(Default)]
+pub struct Toasts {
+    until: HashMap<usize, Instant>,
+}
+
+impl Toasts {
+    pub fn show(&mut self, pad: usize, text: String, now: Instant, store: &mut ValueStore) {
+        store.get(&toast_key(pad)).replace(Box::new(text), store);
+        self.until.insert(pad, now + TOAST_DURATION);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.until.is_empty()
+    }
+
+    // clears toasts that were up long enough
+    pub fn expire(&mut self, now: Instant, store: &mut ValueStore) {
+        self.until.retain(|pad, until| {
+            if now < *until {
+                return true;
+            }
+            store
+                .get(&toast_key(*pad))
+                .replace(Box::new(String::new()), store);
+            false
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PADS: &str = r#"
+[[pad]]
+name = "Wireless Controller"
+vendor = 0x054c
+profile = "ds4.toml"
+
+[[pad]]
+uuid = "03000000-5e04-0000-ea02-000000000000"
+profile = "xbox.toml"
+
+[[pad]]
+vendor = 0x054c
+profile = "sony.toml"
+"#;
+
+    fn info(name: &str, uuid: [u8; 16], vendor: Option<u16>) -> PadInfo {
+        PadInfo {
+            name: name.to_string(),
+            uuid,
+            vendor,
+            product: None,
+        }
+    }
+
+    fn rejected(src: &str) -> (Option<usize>, String) {
+        let e = parse(src).unwrap_err();
+        (e.line, e.message)
+    }
+
+    fn pad(info: PadInfo, slot: usize, profile: Option<PathBuf>) -> Pad {
+        Pad {
+            info,
+            mapper: Mapper::new(slot, Profile::parse("").unwrap(), &mut ValueStore::new()),
+            profile,
+            state: BasicControllerState::default(),
+        }
+    }
+
+    const XBOX: [u8; 16] = [3, 0, 0, 0, 0x5e, 4, 0, 0, 0xea, 2, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn rules_parse() {
+        let rules = parse(PADS).unwrap();
+        assert_eq!(rules.len(), 3);
+        assert_eq!(
+            rules[0],
+            PadRule {
+                name: Some("Wireless Controller".to_string()),
+                uuid: None,
+                vendor: Some(0x054c),
+                product: None,
+                profile: PathBuf::from("ds4.toml"),
+            }
+        );
+        assert_eq!(rules[1].uuid, Some(XBOX));
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn broken_rules_point_at_their_line() {
+        assert_eq!(
+            rejected("[[pad]]\nprofile = \"a.toml\"\n"),
+            (
+                Some(1),
+                "pad needs a name, uuid, vendor or product to match".to_string()
+            )
+        );
+        assert_eq!(
+            rejected("[[pad]]\nname = \"x\"\nuuid = \"0300\"\nprofile = \"a.toml\"\n"),
+            (Some(3), "uuid is not 32 hex digits".to_string())
+        );
+        assert_eq!(
+            rejected("[[pad]]\nname = \"x\"\nprofile = \" \"\n"),
+            (Some(3), "pad profile is empty".to_string())
+        );
+        let (line, message) = rejected("[[pad]]\nname = \"x\"\nprofil = \"a.toml\"\n");
+        assert_eq!(line, Some(3));
+        assert!(message.contains("unknown field `profil`"), "{}", message);
+    }
+
+    #[test]
+    fn uuids_are_32_hex_digits() {
+        assert_eq!(parse_uuid("030000005e040000ea02000000000000"), Some(XBOX));
+        assert_eq!(
+            parse_uuid("03000000-5e04-0000-ea02-000000000000"),
+            Some(XBOX)
+        );
+        assert_eq!(parse_uuid("030000005E040000EA02000000000000"), Some(XBOX));
+        assert_eq!(parse_uuid("030000005e040000ea020000000000"), None);
+        assert_eq!(parse_uuid("030000005e040000ea0200000000000000"), None);
+        assert_eq!(parse_uuid("zz0000005e040000ea02000000000000"), None);
+        // 32 bytes, but not 32 digits
+        assert_eq!(parse_uuid("é0000005e040000ea02000000000000"), None);
+        assert_eq!(uuid_string(&XBOX), "030000005e040000ea02000000000000");
+        assert_eq!(parse_uuid(&uuid_string(&XBOX)), Some(XBOX));
+    }
+
+    #[test]
+    fn the_first_matching_rule_assigns_the_profile() {
+        let rules = parse(PADS).unwrap();
+        let assigned = |info: &PadInfo| assign(&rules, info).map(Path::to_path_buf);
+        let ds4 = info("Wireless Controller", [1; 16], Some(0x054c));
+        assert_eq!(assigned(&ds4), Some(PathBuf::from("ds4.toml")));
+        // every field a rule gives has to match
+        let other_sony = info("DualSense", [1; 16], Some(0x054c));
+        assert_eq!(assigned(&other_sony), Some(PathBuf::from("sony.toml")));
+        let no_vendor = info("Wireless Controller", [1; 16], None);
+        assert_eq!(assigned(&no_vendor), None);
+        let xbox = info("Xbox Controller", XBOX, Some(0x045e));
+        assert_eq!(assigned(&xbox), Some(PathBuf::from("xbox.toml")));
+        assert_eq!(assigned(&info("Xbox Controller", [2; 16], None)), None);
+    }
+
+    #[test]
+    fn pads_get_the_slot_they_want_if_it_is_free() {
+        let pads = [
+            pad(info("a", [1; 16], None), 0, None),
+            pad(info("b", [2; 16], None), 2, None),
+        ];
+        assert_eq!(free_slot(pads.iter(), None), 1);
+        assert_eq!(free_slot(pads.iter(), Some(2)), 1);
+        assert_eq!(free_slot(pads.iter(), Some(3)), 3);
+        assert_eq!(free_slot(pads[..1].iter(), Some(0)), 1);
+        assert_eq!(free_slot([].iter(), Some(1)), 1);
+    }
+
+    #[test]
+    fn pads_that_come_back_get_their_profile_and_slot() {
+        let path = std::env::temp_dir().join(format!("padmixer-pads-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_B\" }\n",
+        )
+        .unwrap();
+        let rules = vec![
+            PadRule {
+                name: Some("assigned".to_string()),
+                uuid: None,
+                vendor: None,
+                product: None,
+                profile: path.clone(),
+            },
+            PadRule {
+                name: Some("missing".to_string()),
+                uuid: None,
+                vendor: None,
+                product: None,
+                profile: path.with_extension("missing"),
+            },
+        ];
+        let mut profiles = Profiles::new(Profile::parse("").unwrap(), rules);
+
+        let assigned = info("assigned", [1; 16], None);
+        assert_eq!(profiles.pick(&assigned), (Some(path.clone()), None));
+        assert_eq!(profiles.get(Some(&path)).base.buttons.len(), 1);
+        // a profile that doesn't load leaves the pad on the default
+        let missing = info("missing", [2; 16], None);
+        assert_eq!(profiles.pick(&missing), (None, None));
+
+        profiles.remember(&pad(assigned.clone(), 3, Some(path.clone())));
+        profiles.remember(&pad(missing.clone(), 1, None));
+        assert_eq!(profiles.pick(&assigned), (Some(path.clone()), Some(3)));
+        assert_eq!(profiles.pick(&missing), (None, Some(1)));
+        // without a uuid there is no telling pads apart
+        let anonymous = info("assigned", [0; 16], None);
+        profiles.remember(&pad(anonymous.clone(), 2, Some(path.clone())));
+        assert_eq!(profiles.pick(&anonymous), (Some(path.clone()), None));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn toasts_clear_after_a_while() {
+        let mut store = ValueStore::new();
+        for pad in 0..2 {
+            store.insert(&toast_key(pad), String::new());
+        }
+        let toast = |store: &ValueStore, pad| {
+            let value = store.get(&toast_key(pad));
+            value
+                .load(store)
+                .as_any()
+                .downcast_ref::<String>()
+                .unwrap()
+                .clone()
+        };
+        let start = Instant::now();
+        let mut toasts = Toasts::default();
+        toasts.show(0, "pad 1 connected".to_string(), start, &mut store);
+        toasts.show(
+            1,
+            "pad 2 connected".to_string(),
+            start + Duration::from_secs(1),
+            &mut store,
+        );
+        toasts.expire(
+            start + TOAST_DURATION - Duration::from_millis(1),
+            &mut store,
+        );
+        assert_eq!(toast(&store, 0), "pad 1 connected");
+        toasts.expire(start + TOAST_DURATION, &mut store);
+        assert_eq!(toast(&store, 0), "");
+        assert_eq!(toast(&store, 1), "pad 2 connected");
+        assert!(!toasts.is_empty());
+        // a newer toast starts the wait over
+        toasts.show(
+            1,
+            "pad 2 disconnected".to_string(),
+            start + Duration::from_secs(3),
+            &mut store,
+        );
+        toasts.expire(start + Duration::from_secs(5), &mut store);
+        assert_eq!(toast(&store, 1), "pad 2 disconnected");
+        toasts.expire(start + Duration::from_secs(6), &mut store);
+        assert_eq!(toast(&store, 1), "");
+        assert!(toasts.is_empty());
+    }
+}
@@ -0,0 +1,359 @@
+use std::time::Duration;
+
+use evdev::{InputEvent, RelativeAxisType};
+
+use crate::binding::{rel_event, BUTTON_PRESS};
+use crate::output::OutputSink;
+use crate::profile::{Pointer, Scroll};
+use crate::types::{BasicControllerState, CommonAnalog};
+
+pub const DEFAULT_POINTER_SPEED: f32 = 1000.0;
+pub const DEFAULT_PRECISION_SPEED: f32 = 0.25;
+pub const DEFAULT_SCROLL_SPEED: f32 = 10.0;
+
+// REL_WHEEL_HI_RES units per notch of REL_WHEEL
+const HI_RES_NOTCH: i32 = 120;
+
+// moves the cursor from a stick on every frame tick, so it keeps moving
+// while the stick is held still and no input events arrive.
+// fractions of a pixel are carried over to the next tick.
+#[derive(Default)]
+pub struct PointerDriver {
+    remainder: (f32, f32),
+}
+
+impl PointerDriver {
+    // one tick of `interval` at the current deflection
+    pub fn tick(
+        &mut self,
+        config: Option<&Pointer>,
+        state: &BasicControllerState,
+        interval: Duration,
+        out: &mut dyn OutputSink,
+    ) {
+        let Some(config) = config else {
+            self.remainder = (0.0, 0.0);
+            return;
+        };
+        let (x, y) = config.source.position(state);
+        let length = x.hypot(y);
+        if length == 0.0 {
+            self.remainder = (0.0, 0.0);
+            return;
+        }
+
+        let mut speed = config.speed * config.acceleration.apply(length.min(1.0));
+        let precise = config
+            .precision
+            .is_some_and(|button| state.button_state_by_type(button).value > BUTTON_PRESS);
+        if precise {
+            speed *= config.precision_speed;
+        }
+        let distance = speed * interval.as_secs_f32();
+        // screen y grows downwards
+        let dx = x / length * distance + self.remainder.0;
+        let dy = -y / length * distance + self.remainder.1;
+        self.remainder = (dx.fract(), dy.fract());
+
+        let mut events = Vec::new();
+        if dx.trunc() != 0.0 {
+            events.push(rel_event(RelativeAxisType::REL_X, dx.trunc() as i32));
+        }
+        if dy.trunc() != 0.0 {
+            events.push(rel_event(RelativeAxisType::REL_Y, dy.trunc() as i32));
+        }
+        if !events.is_empty() {
+            out.send(&events);
+        }
+    }
+}
+
+// one scroll wheel, sending both hi-res units and the whole notches older clients read
+#[derive(Default)]
+struct Wheel {
+    // turned this far but not sent yet, in hi-res units or notches
+    remainder: f32,
+    // hi-res units sent since the last whole notch
+    partial: i32,
+    turning: bool,
+}
+
+impl Wheel {
+    fn turn(
+        &mut self,
+        notches: f32,
+        notch: bool,
+        axes: (RelativeAxisType, RelativeAxisType),
+        events: &mut Vec<InputEvent>,
+    ) {
+        let (legacy, hi_res) = axes;
+        if notches == 0.0 {
+            *self = Self::default();
+            return;
+        }
+
+        let (whole, units) = if notch {
+            // the first notch goes out right away, later ones repeat at the speed
+            let turned = if self.turning {
+                notches + self.remainder
+            } else {
+                notches.signum()
+            };
+            self.remainder = turned.fract();
+            let whole = turned.trunc() as i32;
+            (whole, whole * HI_RES_NOTCH)
+        } else {
+            let turned = notches * HI_RES_NOTCH as f32 + self.remainder;
+            self.remainder = turned.fract();
+            self.partial += turned.trunc() as i32;
+            let whole = self.partial / HI_RES_NOTCH;
+            self.partial -= whole * HI_RES_NOTCH;
+            (whole, turned.trunc() as i32)
+        };
+        self.turning = true;
+
+        if whole != 0 {
+            events.push(rel_event(legacy, whole));
+        }
+        if units != 0 {
+            events.push(rel_event(hi_res, units));
+        }
+    }
+}
+
+// turns the scroll wheels from sticks and triggers on every frame tick,
+// as fast as the input is pushed
+#[derive(Default)]
+pub struct ScrollDriver {
+    vertical: Wheel,
+    horizontal: Wheel,
+}
+
+impl ScrollDriver {
+    pub fn tick(
+        &mut self,
+        config: Option<&Scroll>,
+        state: &BasicControllerState,
+        interval: Duration,
+        out: &mut dyn OutputSink,
+    ) {
+        let Some(config) = config else {
+            self.vertical = Wheel::default();
+            self.horizontal = Wheel::default();
+            return;
+        };
+        let (x, mut y) = config
+            .source
+            .map(|source| source.position(state))
+            .unwrap_or((0.0, 0.0));
+        let pull = |analog: Option<CommonAnalog>| {
+            analog.map_or(0.0, |analog| {
+                state.analog_state_by_type(analog).value.max(0.0)
+            })
+        };
+        y = (y + pull(config.up) - pull(config.down)).clamp(-1.0, 1.0);
+
+        let notches = config.speed * interval.as_secs_f32();
+        let mut events = Vec::new();
+        self.vertical.turn(
+            y * notches,
+            config.notch,
+            (
+                RelativeAxisType::REL_WHEEL,
+                RelativeAxisType::REL_WHEEL_HI_RES,
+            ),
+            &mut events,
+        );
+        self.horizontal.turn(
+            x * notches,
+            config.notch,
+            (
+                RelativeAxisType::REL_HWHEEL,
+                RelativeAxisType::REL_HWHEEL_HI_RES,
+            ),
+            &mut events,
+        );
+        if !events.is_empty() {
+            out.send(&events);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{describe_frame, Recorder};
+    use crate::profile::Profile;
+    use crate::types::CommonButton;
+
+    // an eighth of a second, so distances per tick come out exact
+    const TICK: Duration = Duration::from_millis(125);
+
+    // what `ticks` ticks sent, one description per tick with "" for ticks that sent nothing
+    fn run(ticks: usize, mut tick: impl FnMut(&mut Recorder)) -> Vec<String> {
+        (0..ticks)
+            .map(|_| {
+                let mut out = Recorder::default();
+                tick(&mut out);
+                let sent: Vec<String> = out.frames.iter().map(|f| describe_frame(f)).collect();
+                sent.join(" | ")
+            })
+            .collect()
+    }
+
+    fn pointer(
+        driver: &mut PointerDriver,
+        profile: &Profile,
+        state: &BasicControllerState,
+        ticks: usize,
+    ) -> Vec<String> {
+        run(ticks, |out| {
+            driver.tick(profile.base.pointer.as_ref(), state, TICK, out)
+        })
+    }
+
+    const POINTER: &str =
+        "[pointer]\nsource = \"LeftStick\"\nspeed = 100\nprecision = \"LegacyLT\"\n";
+
+    #[test]
+    fn fractions_of_a_pixel_carry_over() {
+        let profile = Profile::parse(POINTER).unwrap();
+        let mut driver = PointerDriver::default();
+        let mut state = BasicControllerState::default();
+        // 6.25 pixels a tick
+        state.try_update_analog(CommonAnalog::LeftStickX, 0.5);
+        assert_eq!(
+            pointer(&mut driver, &profile, &state, 4),
+            ["REL_X 6", "REL_X 6", "REL_X 6", "REL_X 7"]
+        );
+        // a quarter pixel a tick, screen y pointing down
+        state.try_update_analog(CommonAnalog::LeftStickX, 0.0);
+        state.try_update_analog(CommonAnalog::LeftStickY, 0.02);
+        assert_eq!(
+            pointer(&mut driver, &profile, &state, 8),
+            ["", "", "", "REL_Y -1", "", "", "", "REL_Y -1"]
+        );
+    }
+
+    #[test]
+    fn the_remainder_is_dropped_at_center() {
+        let profile = Profile::parse(POINTER).unwrap();
+        let mut driver = PointerDriver::default();
+        let mut state = BasicControllerState::default();
+        state.try_update_analog(CommonAnalog::LeftStickX, 0.06);
+        assert_eq!(pointer(&mut driver, &profile, &state, 1), [""]);
+        state.try_update_analog(CommonAnalog::LeftStickX, 0.0);
+        assert_eq!(pointer(&mut driver, &profile, &state, 1), [""]);
+        // 0.75 a tick, after the 0.75 before center was let go
+        state.try_update_analog(CommonAnalog::LeftStickX, 0.06);
+        assert_eq!(pointer(&mut driver, &profile, &state, 2), ["", "REL_X 1"]);
+    }
+
+    #[test]
+    fn precision_slows_the_pointer() {
+        let profile = Profile::parse(POINTER).unwrap();
+        let mut driver = PointerDriver::default();
+        let mut state = BasicControllerState::default();
+        state.try_update_analog(CommonAnalog::LeftStickX, -1.0);
+        assert_eq!(pointer(&mut driver, &profile, &state, 1), ["REL_X -12"]);
+        // a quarter of 12.5, on top of the half pixel left over
+        state.try_update_button(CommonButton::LegacyLT, 1.0);
+        assert_eq!(
+            pointer(&mut driver, &profile, &state, 2),
+            ["REL_X -3", "REL_X -3"]
+        );
+    }
+
+    fn scroll(
+        driver: &mut ScrollDriver,
+        profile: &Profile,
+        state: &BasicControllerState,
+        ticks: usize,
+    ) -> Vec<String> {
+        run(ticks, |out| {
+            driver.tick(profile.base.scroll.as_ref(), state, TICK, out)
+        })
+    }
+
+    // a notch a tick at full deflection
+    const SCROLL: &str = "[scroll]\nsource = \"RightStick\"\nspeed = 8\n";
+
+    #[test]
+    fn hi_res_units_add_up_to_notches() {
+        let profile = Profile::parse(SCROLL).unwrap();
+        let mut driver = ScrollDriver::default();
+        let mut state = BasicControllerState::default();
+        state.try_update_analog(CommonAnalog::RightStickY, 0.5);
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 4),
+            [
+                "REL_WHEEL_HI_RES 60",
+                "REL_WHEEL 1, REL_WHEEL_HI_RES 60",
+                "REL_WHEEL_HI_RES 60",
+                "REL_WHEEL 1, REL_WHEEL_HI_RES 60",
+            ]
+        );
+        state.try_update_analog(CommonAnalog::RightStickY, -0.5);
+        state.try_update_analog(CommonAnalog::RightStickX, 1.0);
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 2),
+            [
+                "REL_WHEEL_HI_RES -60, REL_HWHEEL 1, REL_HWHEEL_HI_RES 120",
+                "REL_WHEEL -1, REL_WHEEL_HI_RES -60, REL_HWHEEL 1, REL_HWHEEL_HI_RES 120",
+            ]
+        );
+    }
+
+    #[test]
+    fn fractions_of_a_hi_res_unit_carry_over() {
+        let profile = Profile::parse(SCROLL).unwrap();
+        let mut driver = ScrollDriver::default();
+        let mut state = BasicControllerState::default();
+        // 0.46875 units a tick
+        state.try_update_analog(CommonAnalog::RightStickY, 1.0 / 256.0);
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 5),
+            ["", "", "REL_WHEEL_HI_RES 1", "", "REL_WHEEL_HI_RES 1"]
+        );
+    }
+
+    #[test]
+    fn notch_mode_sends_the_first_notch_right_away() {
+        let profile = Profile::parse(&format!("{SCROLL}notch = true\n")).unwrap();
+        let mut driver = ScrollDriver::default();
+        let mut state = BasicControllerState::default();
+        state.try_update_analog(CommonAnalog::RightStickY, 0.5);
+        let notch = "REL_WHEEL 1, REL_WHEEL_HI_RES 120";
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 4),
+            [notch, "", notch, ""]
+        );
+        // back at center the next push starts over
+        state.try_update_analog(CommonAnalog::RightStickY, 0.0);
+        assert_eq!(scroll(&mut driver, &profile, &state, 1), [""]);
+        state.try_update_analog(CommonAnalog::RightStickY, 0.5);
+        assert_eq!(scroll(&mut driver, &profile, &state, 2), [notch, ""]);
+    }
+
+    #[test]
+    fn triggers_scroll_up_and_down() {
+        let profile =
+            Profile::parse("[scroll]\nup = \"LeftLever\"\ndown = \"RightLever\"\nspeed = 8\n")
+                .unwrap();
+        let mut driver = ScrollDriver::default();
+        let mut state = BasicControllerState::default();
+        state.try_update_analog(CommonAnalog::RightLever, 1.0);
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 1),
+            ["REL_WHEEL -1, REL_WHEEL_HI_RES -120"]
+        );
+        // pulled together they cancel out
+        state.try_update_analog(CommonAnalog::LeftLever, 1.0);
+        assert_eq!(scroll(&mut driver, &profile, &state, 1), [""]);
+        state.try_update_analog(CommonAnalog::RightLever, 0.0);
+        assert_eq!(
+            scroll(&mut driver, &profile, &state, 1),
+            ["REL_WHEEL 1, REL_WHEEL_HI_RES 120"]
+        );
+    }
+}
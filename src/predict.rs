@@ -0,0 +1,259 @@
+use std::path::Path;
+
+use evdev::{InputEvent, Key};
+
+use crate::binding::{char_to_key, Action, BUTTON_PRESS};
+use crate::profile::{config_dir, TextEntry};
+use crate::types::BasicControllerState;
+use crate::RES;
+
+// words in the order they are suggested, most common first
+pub struct Dictionary {
+    words: Vec<String>,
+}
+
+impl Dictionary {
+    // one word per line. blank lines, lines starting with '#' and
+    // words that can not be typed are skipped.
+    pub fn parse(src: &str) -> Self {
+        let mut words: Vec<String> = Vec::new();
+        for line in src.lines() {
+            let word = line.trim().to_lowercase();
+            if word.is_empty()
+                || word.starts_with('#')
+                || word.chars().any(|ch| char_to_key(ch).is_none())
+                || words.contains(&word)
+            {
+                continue;
+            }
+            words.push(word);
+        }
+        Self { words }
+    }
+
+    // the profile's dictionary file, relative to the config directory,
+    // or the bundled word list
+    pub fn load(path: Option<&Path>) -> Self {
+        if let Some(path) = path {
+            let path = match config_dir() {
+                Some(dir) if path.is_relative() => dir.join(path),
+                _ => path.to_path_buf(),
+            };
+            match std::fs::read_to_string(&path) {
+                Ok(src) => return Self::parse(&src),
+                Err(e) => println!("{}: {}", path.display(), e),
+            }
+        }
+        Self::parse(RES.get_file("words.txt").unwrap().contents_utf8().unwrap())
+    }
+
+    pub fn complete(&self, prefix: &str, count: usize) -> Vec<String> {
+        if prefix.is_empty() {
+            return Vec::new();
+        }
+        self.words
+            .iter()
+            .filter(|word| word.len() > prefix.len() && word.starts_with(prefix))
+            .take(count)
+            .cloned()
+            .collect()
+    }
+}
+
+// follows the word being typed on a radial menu and offers completions for it.
+// one button moves the highlight through the completions, another types the rest
+// of the highlighted word followed by a space.
+pub struct Predictor {
+    config: TextEntry,
+    dictionary: Dictionary,
+    word: String,
+    completions: Vec<String>,
+    highlighted: usize,
+    select_down: bool,
+    accept_down: bool,
+}
+
+impl Predictor {
+    pub fn new(config: TextEntry) -> Self {
+        let dictionary = Dictionary::load(config.dictionary.as_deref());
+        Self {
+            config,
+            dictionary,
+            word: String::new(),
+            completions: Vec::new(),
+            highlighted: 0,
+            select_down: false,
+            accept_down: false,
+        }
+    }
+
+    pub fn config(&self) -> &TextEntry {
+        &self.config
+    }
+
+    pub fn completions(&self) -> &[String] {
+        &self.completions
+    }
+
+    pub fn highlighted(&self) -> Option<usize> {
+        Some(self.highlighted).filter(|_| !self.completions.is_empty())
+    }
+
+    fn set_word(&mut self, word: String) {
+        self.completions = self.dictionary.complete(&word, self.config.suggestions);
+        self.highlighted = 0;
+        self.word = word;
+    }
+
+    // a key typed from the radial menu. letters extend the word,
+    // backspace shortens it and anything else ends it.
+    pub fn typed(&mut self, key: Key) {
+        let mut word = std::mem::take(&mut self.word);
+        if key == Key::KEY_BACKSPACE {
+            word.pop();
+        } else {
+            match ('a'..='z').find(|ch| char_to_key(*ch) == Some((key, false))) {
+                Some(ch) => word.push(ch),
+                None => word.clear(),
+            }
+        }
+        self.set_word(word);
+    }
+
+    // reads the select and accept buttons, writing an accepted completion to `events`
+    pub fn update(&mut self, state: &BasicControllerState, events: &mut Vec<InputEvent>) {
+        let down = |button| state.button_state_by_type(button).value > BUTTON_PRESS;
+        let select_down = down(self.config.select);
+        let accept_down = down(self.config.accept);
+
+        if select_down && !self.select_down && !self.completions.is_empty() {
+            self.highlighted = (self.highlighted + 1) % self.completions.len();
+        }
+        if accept_down && !self.accept_down {
+            if let Some(word) = self.completions.get(self.highlighted) {
+                let rest = format!("{} ", &word[self.word.len()..]);
+                Action::Text(rest).press(events);
+                self.set_word(String::new());
+            }
+        }
+        self.select_down = select_down;
+        self.accept_down = accept_down;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::describe_frame;
+    use crate::types::CommonButton;
+
+    const WORDS: &str = "# most common first\nthe\nthat\nthen\nThe\nthem\n\nthere\ncafé\nto\n";
+
+    fn predictor(suggestions: usize) -> Predictor {
+        let config = TextEntry {
+            dictionary: None,
+            select: CommonButton::LegacyRT,
+            accept: CommonButton::LegacyLT,
+            suggestions,
+        };
+        Predictor {
+            config,
+            dictionary: Dictionary::parse(WORDS),
+            word: String::new(),
+            completions: Vec::new(),
+            highlighted: 0,
+            select_down: false,
+            accept_down: false,
+        }
+    }
+
+    fn type_word(predictor: &mut Predictor, word: &str) {
+        for ch in word.chars() {
+            predictor.typed(char_to_key(ch).unwrap().0);
+        }
+    }
+
+    // presses and lets go of a button, returning what was typed
+    fn tap(predictor: &mut Predictor, button: CommonButton) -> String {
+        let mut state = BasicControllerState::default();
+        let mut events = Vec::new();
+        state.try_update_button(button, 1.0);
+        predictor.update(&state, &mut events);
+        state.try_update_button(button, 0.0);
+        predictor.update(&state, &mut events);
+        describe_frame(&events)
+    }
+
+    #[test]
+    fn words_are_ranked_in_file_order() {
+        let dictionary = Dictionary::parse(WORDS);
+        // comments, blank lines, repeats and untypable words are skipped
+        assert_eq!(
+            dictionary.words,
+            ["the", "that", "then", "them", "there", "to"]
+        );
+        assert_eq!(dictionary.complete("th", 3), ["the", "that", "then"]);
+        assert_eq!(dictionary.complete("th", 10).len(), 5);
+    }
+
+    #[test]
+    fn only_longer_words_with_the_prefix_complete() {
+        let dictionary = Dictionary::parse(WORDS);
+        assert_eq!(dictionary.complete("the", 5), ["then", "them", "there"]);
+        assert_eq!(dictionary.complete("then", 5), Vec::<String>::new());
+        assert_eq!(dictionary.complete("x", 5), Vec::<String>::new());
+        assert_eq!(dictionary.complete("", 5), Vec::<String>::new());
+    }
+
+    #[test]
+    fn typed_keys_follow_the_word() {
+        let mut predictor = predictor(2);
+        type_word(&mut predictor, "th");
+        assert_eq!(predictor.completions(), ["the", "that"]);
+        assert_eq!(predictor.highlighted(), Some(0));
+        predictor.typed(Key::KEY_E);
+        assert_eq!(predictor.completions(), ["then", "them"]);
+        predictor.typed(Key::KEY_BACKSPACE);
+        predictor.typed(Key::KEY_BACKSPACE);
+        assert_eq!(predictor.completions(), ["the", "that"]);
+        // anything but a letter ends the word
+        predictor.typed(Key::KEY_SPACE);
+        assert!(predictor.completions().is_empty());
+        assert_eq!(predictor.highlighted(), None);
+        predictor.typed(Key::KEY_BACKSPACE);
+        assert!(predictor.completions().is_empty());
+    }
+
+    #[test]
+    fn select_moves_the_highlight_and_accept_types_the_rest() {
+        let mut predictor = predictor(3);
+        assert_eq!(tap(&mut predictor, CommonButton::LegacyLT), "");
+        type_word(&mut predictor, "th");
+        assert_eq!(tap(&mut predictor, CommonButton::LegacyRT), "");
+        assert_eq!(predictor.highlighted(), Some(1));
+        tap(&mut predictor, CommonButton::LegacyRT);
+        tap(&mut predictor, CommonButton::LegacyRT);
+        assert_eq!(predictor.highlighted(), Some(0));
+        tap(&mut predictor, CommonButton::LegacyRT);
+        assert_eq!(
+            tap(&mut predictor, CommonButton::LegacyLT),
+            "KEY_A down, KEY_A up, KEY_T down, KEY_T up, KEY_SPACE down, KEY_SPACE up"
+        );
+        // the accepted word is done
+        assert!(predictor.completions().is_empty());
+        type_word(&mut predictor, "the");
+        assert_eq!(predictor.completions(), ["then", "them", "there"]);
+    }
+
+    #[test]
+    fn a_held_button_acts_once() {
+        let mut predictor = predictor(3);
+        type_word(&mut predictor, "th");
+        let mut state = BasicControllerState::default();
+        let mut events = Vec::new();
+        state.try_update_button(CommonButton::LegacyRT, 1.0);
+        predictor.update(&state, &mut events);
+        predictor.update(&state, &mut events);
+        assert_eq!(predictor.highlighted(), Some(1));
+    }
+}
@@ -0,0 +1,1440 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    ops::Range,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use evdev::Key;
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{MapAccess, SeqAccess, Visitor};
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::analog::{AxisSettings, Curve};
+use crate::binding::{char_to_key, is_mouse_button, Action};
+use crate::gamepad::DEFAULT_GAMEPAD_NAME;
+use crate::kill_switch::DEFAULT_KILL_SWITCH_HOLD_MS;
+use crate::pointer::{DEFAULT_POINTER_SPEED, DEFAULT_PRECISION_SPEED, DEFAULT_SCROLL_SPEED};
+use crate::radial::{RadialSource, DEFAULT_HYSTERESIS, DEFAULT_MENU_TIMEOUT_MS};
+use crate::types::{CommonAnalog, CommonButton, ListItemData};
+
+pub const PROFILE_FILE: &str = "profile.toml";
+
+// typed profile, produced only by `Profile::parse` after validation
+
+#[derive(Clone)]
+pub struct Profile {
+    pub thresholds: Thresholds,
+    pub input: InputSettings,
+    // held on the raw pad to pause and resume all output, outside of every layer
+    pub kill_switch: KillChord,
+    // mirror the pad, minus what the active layers use, on a virtual gamepad
+    pub gamepad: Option<Gamepad>,
+    // deadzones and curves per analog. analogs not listed pass through raw.
+    pub axes: HashMap<CommonAnalog, AxisSettings>,
+    // always active. named layers stack on top of it, later layers winning.
+    pub base: Layer,
+    pub layers: Vec<Layer>,
+}
+
+#[derive(Clone)]
+pub struct Layer {
+    pub name: String,
+    pub buttons: Vec<ButtonBind>,
+    pub analogs: Vec<AnalogBind>,
+    // at most one per source. sources without one keep the radial menu of the layers below.
+    pub radials: Vec<Radial>,
+    // layers without one keep the pointer of the layers below
+    pub pointer: Option<Pointer>,
+    // likewise for scrolling
+    pub scroll: Option<Scroll>,
+}
+
+// how the physical pad is read
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct InputSettings {
+    pub backend: Backend,
+    // evdev backend only, a /dev/input path or device name to read instead of the first pad
+    pub device: Option<String>,
+    // hide the pad from other programs and read it through evdev directly
+    pub grab: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct KillChord {
+    // all held together
+    pub buttons: Vec<CommonButton>,
+    pub hold_ms: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    #[default]
+    Gilrs,
+    Evdev,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gamepad {
+    // device name other programs see
+    pub name: String,
+    // physical button to the button the virtual pad reports for it
+    pub remap: HashMap<CommonButton, CommonButton>,
+}
+
+#[derive(Copy, Clone)]
+pub struct Thresholds {
+    // stick magnitude before the radial menu presses anything
+    pub radial: f32,
+}
+
+#[derive(Clone)]
+pub struct ButtonBind {
+    pub button: CommonButton,
+    pub action: Action,
+}
+
+// an analog used as a button
+#[derive(Clone)]
+pub struct AnalogBind {
+    pub analog: CommonAnalog,
+    // which side of center a stick axis is read on
+    pub direction: Direction,
+    pub threshold: Threshold,
+    pub action: Action,
+    // second stage further along the travel, held on top of the first
+    pub full: Option<FullPull>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Positive,
+    Negative,
+}
+
+// the value must exceed `press` to press, and drop to `release` or below to release
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Threshold {
+    pub press: f32,
+    pub release: f32,
+}
+
+#[derive(Clone)]
+pub struct FullPull {
+    pub threshold: Threshold,
+    pub action: Action,
+}
+
+// a stick or the d-pad moving the mouse cursor
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pointer {
+    pub source: RadialSource,
+    // pixels per second at full deflection
+    pub speed: f32,
+    // share of the speed for a deflection, both 0..1
+    pub acceleration: Curve,
+    // scales the speed by `precision_speed` while held
+    pub precision: Option<CommonButton>,
+    pub precision_speed: f32,
+}
+
+// sticks and triggers turning the scroll wheels
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scroll {
+    // stick or d-pad, up and right scroll up and right
+    pub source: Option<RadialSource>,
+    // analogs whose pull scrolls up or down, for triggers
+    pub up: Option<CommonAnalog>,
+    pub down: Option<CommonAnalog>,
+    // wheel notches per second at full deflection
+    pub speed: f32,
+    // whole notches only, repeating faster the further the input is pushed
+    pub notch: bool,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Radial {
+    // stick or d-pad that drives the menu. only the top ring's setting is used.
+    pub source: RadialSource,
+    // where and how the menu is drawn. only the top ring's setting is used.
+    pub overlay: Overlay,
+    pub keys: Vec<RadialKey>,
+    // degrees counter-clockwise from the right where the first key sits
+    pub start_angle: f32,
+    pub clockwise: bool,
+    // degrees the stick must move past a sector's edge before the selection changes
+    pub hysteresis: f32,
+    // how long a new sector must stay selected before it counts
+    pub dwell_ms: u64,
+    // type the selected key once the stick returns to center, instead of holding it
+    pub commit_on_release: bool,
+    // leaves a submenu for its parent. only the top ring's setting is used.
+    pub back: Option<CommonButton>,
+    // idle time in a submenu before falling back to the top ring, 0 to stay.
+    // only the top ring's setting is used.
+    pub timeout_ms: u64,
+    // word completion for what is typed on the menu. only the top ring's setting is used.
+    pub text_entry: Option<TextEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEntry {
+    // word list, most common first. relative to the config directory.
+    // none for the bundled list.
+    pub dictionary: Option<PathBuf>,
+    // moves the highlight to the next completion
+    pub select: CommonButton,
+    // types the rest of the highlighted completion
+    pub accept: CommonButton,
+    // how many completions are offered at once
+    pub suggestions: usize,
+}
+
+impl Radial {
+    // keys of this ring and all of its submenus
+    pub fn keys(&self) -> Vec<Key> {
+        self.keys
+            .iter()
+            .flat_map(|item| match &item.target {
+                RadialTarget::Key(key) => vec![*key],
+                RadialTarget::Menu(menu) => menu.keys(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct RadialKey {
+    pub label: char,
+    pub target: RadialTarget,
+    // relative size of the key's sector
+    pub weight: f32,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum RadialTarget {
+    Key(Key),
+    // opens a child ring once the stick returns to center
+    Menu(Radial),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Overlay {
+    pub anchor: Anchor,
+    // distance from the anchored screen edges, x then y
+    pub margin: [i32; 2],
+    // width and height of the overlay window
+    pub size: i32,
+    pub theme: Theme,
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<Overlay {:?} {:?}>", self.anchor, self.margin)
+    }
+}
+
+impl ListItemData for Overlay {}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub ring: Rgba,
+    pub marker: Rgba,
+    pub text: Rgba,
+    pub selected: Rgba,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            ring: Rgba(128, 128, 225, 128),
+            marker: Rgba(178, 0, 225, 200),
+            text: Rgba(255, 255, 255, 255),
+            selected: Rgba(178, 0, 225, 255),
+        }
+    }
+}
+
+// a color written as "#rrggbb" or "#rrggbbaa"
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
+
+impl TryFrom<String> for Rgba {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid = || format!("{:?} is not a #rrggbb or #rrggbbaa color", value);
+        let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut channels = [255; 4];
+        for (channel, index) in channels.iter_mut().zip((0..hex.len()).step_by(2)) {
+            *channel = u8::from_str_radix(&hex[index..index + 2], 16).map_err(|_| invalid())?;
+        }
+        Ok(Rgba(channels[0], channels[1], channels[2], channels[3]))
+    }
+}
+
+#[derive(Debug)]
+pub struct ProfileError {
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+// raw file layout, spans are kept so validation can point at a line
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawProfile {
+    #[serde(default)]
+    thresholds: RawThresholds,
+    #[serde(default)]
+    input: RawInput,
+    #[serde(default)]
+    kill_switch: RawKillSwitch,
+    gamepad: Option<RawGamepad>,
+    #[serde(default)]
+    axis: HashMap<CommonAnalog, RawAxis>,
+    #[serde(default)]
+    bind: Vec<RawButtonBind>,
+    #[serde(default)]
+    analog: Vec<RawAnalogBind>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    radial: Vec<RawRadial>,
+    pointer: Option<RawPointer>,
+    scroll: Option<Spanned<RawScroll>>,
+    #[serde(default)]
+    layer: Vec<RawLayer>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawLayer {
+    name: Spanned<String>,
+    #[serde(default)]
+    bind: Vec<RawButtonBind>,
+    #[serde(default)]
+    analog: Vec<RawAnalogBind>,
+    #[serde(default, deserialize_with = "one_or_many")]
+    radial: Vec<RawRadial>,
+    pointer: Option<RawPointer>,
+    scroll: Option<Spanned<RawScroll>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawPointer {
+    #[serde(default = "default_pointer_source")]
+    source: RadialSource,
+    #[serde(default = "default_pointer_speed")]
+    speed: Spanned<f32>,
+    #[serde(default = "default_curve")]
+    acceleration: Spanned<Curve>,
+    precision: Option<CommonButton>,
+    #[serde(default = "default_precision_speed")]
+    precision_speed: Spanned<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawScroll {
+    source: Option<RadialSource>,
+    up: Option<CommonAnalog>,
+    down: Option<CommonAnalog>,
+    #[serde(default = "default_scroll_speed")]
+    speed: Spanned<f32>,
+    #[serde(default)]
+    notch: bool,
+}
+
+fn default_scroll_speed() -> Spanned<f32> {
+    Spanned::new(0..0, DEFAULT_SCROLL_SPEED)
+}
+
+fn default_pointer_source() -> RadialSource {
+    RadialSource::LeftStick
+}
+
+fn default_pointer_speed() -> Spanned<f32> {
+    Spanned::new(0..0, DEFAULT_POINTER_SPEED)
+}
+
+fn default_precision_speed() -> Spanned<f32> {
+    Spanned::new(0..0, DEFAULT_PRECISION_SPEED)
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawInput {
+    #[serde(default)]
+    backend: Backend,
+    device: Option<Spanned<String>>,
+    #[serde(default)]
+    grab: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawKillSwitch {
+    #[serde(default = "default_kill_switch_buttons")]
+    buttons: Spanned<Vec<CommonButton>>,
+    #[serde(default = "default_kill_switch_hold_ms")]
+    hold_ms: Spanned<u64>,
+}
+
+impl Default for RawKillSwitch {
+    fn default() -> Self {
+        Self {
+            buttons: default_kill_switch_buttons(),
+            hold_ms: default_kill_switch_hold_ms(),
+        }
+    }
+}
+
+fn default_kill_switch_buttons() -> Spanned<Vec<CommonButton>> {
+    Spanned::new(
+        0..0,
+        vec![
+            CommonButton::Start,
+            CommonButton::Select,
+            CommonButton::Guide,
+        ],
+    )
+}
+
+fn default_kill_switch_hold_ms() -> Spanned<u64> {
+    Spanned::new(0..0, DEFAULT_KILL_SWITCH_HOLD_MS)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawGamepad {
+    #[serde(default = "default_gamepad_name")]
+    name: Spanned<String>,
+    #[serde(default)]
+    remap: HashMap<CommonButton, CommonButton>,
+}
+
+fn default_gamepad_name() -> Spanned<String> {
+    Spanned::new(0..0, DEFAULT_GAMEPAD_NAME.to_string())
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAxis {
+    #[serde(default = "default_zero")]
+    deadzone: Spanned<f32>,
+    #[serde(default = "default_outer")]
+    outer: Spanned<f32>,
+    #[serde(default = "default_zero")]
+    anti_deadzone: Spanned<f32>,
+    #[serde(default = "default_curve")]
+    curve: Spanned<Curve>,
+    #[serde(default)]
+    invert: bool,
+    #[serde(default)]
+    radial: bool,
+}
+
+fn default_zero() -> Spanned<f32> {
+    Spanned::new(0..0, 0.0)
+}
+
+fn default_outer() -> Spanned<f32> {
+    Spanned::new(0..0, 1.0)
+}
+
+fn default_curve() -> Spanned<Curve> {
+    Spanned::new(0..0, Curve::Linear)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawThresholds {
+    #[serde(default = "default_radial_threshold")]
+    radial: Spanned<f32>,
+    #[serde(default = "default_analog_threshold")]
+    analog: Spanned<f32>,
+}
+
+impl Default for RawThresholds {
+    fn default() -> Self {
+        Self {
+            radial: default_radial_threshold(),
+            analog: default_analog_threshold(),
+        }
+    }
+}
+
+fn default_radial_threshold() -> Spanned<f32> {
+    Spanned::new(0..0, 0.5)
+}
+
+fn default_analog_threshold() -> Spanned<f32> {
+    Spanned::new(0..0, 0.0)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawButtonBind {
+    button: Spanned<CommonButton>,
+    action: Spanned<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawAnalogBind {
+    analog: Spanned<CommonAnalog>,
+    #[serde(default = "default_direction")]
+    direction: Spanned<Direction>,
+    press: Option<Spanned<f32>>,
+    release: Option<Spanned<f32>>,
+    action: Spanned<Action>,
+    full: Option<RawFullPull>,
+}
+
+fn default_direction() -> Spanned<Direction> {
+    Spanned::new(0..0, Direction::Positive)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFullPull {
+    press: Spanned<f32>,
+    release: Option<Spanned<f32>>,
+    action: Spanned<Action>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRadial {
+    #[serde(default = "default_source")]
+    source: Spanned<RadialSource>,
+    #[serde(default)]
+    overlay: RawOverlay,
+    #[serde(default)]
+    theme: RawTheme,
+    keys: Spanned<Vec<RawRadialKey>>,
+    #[serde(default)]
+    start_angle: f32,
+    #[serde(default)]
+    clockwise: bool,
+    #[serde(default = "default_hysteresis")]
+    hysteresis: Spanned<f32>,
+    #[serde(default)]
+    dwell_ms: u64,
+    #[serde(default)]
+    commit_on_release: bool,
+    back: Option<CommonButton>,
+    #[serde(default = "default_timeout_ms")]
+    timeout_ms: u64,
+    text_entry: Option<RawTextEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTextEntry {
+    dictionary: Option<PathBuf>,
+    #[serde(default = "default_select")]
+    select: CommonButton,
+    #[serde(default = "default_accept")]
+    accept: CommonButton,
+    #[serde(default = "default_suggestions")]
+    suggestions: Spanned<usize>,
+}
+
+fn default_select() -> CommonButton {
+    CommonButton::LegacyRT
+}
+
+fn default_accept() -> CommonButton {
+    CommonButton::LegacyLT
+}
+
+fn default_suggestions() -> Spanned<usize> {
+    Spanned::new(0..0, 3)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRadialKey {
+    label: Spanned<String>,
+    key: Option<Key>,
+    menu: Option<RawRadial>,
+    #[serde(default = "default_weight")]
+    weight: Spanned<f32>,
+}
+
+fn default_weight() -> Spanned<f32> {
+    Spanned::new(0..0, 1.0)
+}
+
+fn default_hysteresis() -> Spanned<f32> {
+    Spanned::new(0..0, DEFAULT_HYSTERESIS)
+}
+
+// `[radial]` for a single menu, `[[radial]]` for several
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<RawRadial>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    struct OneOrMany;
+
+    impl<'de> Visitor<'de> for OneOrMany {
+        type Value = Vec<RawRadial>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a radial table or an array of radial tables")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Ok(vec![RawRadial::deserialize(MapAccessDeserializer::new(
+                map,
+            ))?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            Vec::deserialize(SeqAccessDeserializer::new(seq))
+        }
+    }
+
+    deserializer.deserialize_any(OneOrMany)
+}
+
+fn default_source() -> Spanned<RadialSource> {
+    Spanned::new(0..0, RadialSource::RightStick)
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawOverlay {
+    anchor: Option<Anchor>,
+    margin: Option<[i32; 2]>,
+    size: Option<Spanned<i32>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTheme {
+    ring: Option<Rgba>,
+    marker: Option<Rgba>,
+    text: Option<Rgba>,
+    selected: Option<Rgba>,
+}
+
+fn default_timeout_ms() -> u64 {
+    DEFAULT_MENU_TIMEOUT_MS
+}
+
+impl Profile {
+    pub fn parse(src: &str) -> Result<Self, ProfileError> {
+        let raw: RawProfile = toml::from_str(src).map_err(|e| ProfileError {
+            line: e.span().map(|span| line_of(src, span)),
+            message: e.message().to_string(),
+        })?;
+        let error = |span: Range<usize>, message: String| ProfileError {
+            line: Some(line_of(src, span)),
+            message,
+        };
+
+        for threshold in [&raw.thresholds.radial, &raw.thresholds.analog] {
+            let value = *threshold.get_ref();
+            if !(0.0..1.0).contains(&value) {
+                return Err(error(
+                    threshold.span(),
+                    format!("threshold {} is outside 0.0..1.0", value),
+                ));
+            }
+        }
+
+        let input = InputSettings {
+            backend: raw.input.backend,
+            device: match raw.input.device {
+                Some(device) => {
+                    if raw.input.backend != Backend::Evdev {
+                        return Err(error(
+                            device.span(),
+                            "input device needs backend = \"evdev\"".to_string(),
+                        ));
+                    }
+                    if device.get_ref().trim().is_empty() {
+                        return Err(error(device.span(), "input device is empty".to_string()));
+                    }
+                    Some(device.into_inner())
+                }
+                None => None,
+            },
+            grab: raw.input.grab,
+        };
+
+        // the chord can be moved, but never turned off
+        let kill_switch = raw.kill_switch;
+        if kill_switch.buttons.get_ref().is_empty() {
+            return Err(error(
+                kill_switch.buttons.span(),
+                "kill switch needs at least one button".to_string(),
+            ));
+        }
+        if *kill_switch.hold_ms.get_ref() == 0 {
+            return Err(error(
+                kill_switch.hold_ms.span(),
+                "kill switch hold_ms must be above 0".to_string(),
+            ));
+        }
+        let kill_switch = KillChord {
+            hold_ms: *kill_switch.hold_ms.get_ref(),
+            buttons: kill_switch.buttons.into_inner(),
+        };
+
+        let gamepad = match raw.gamepad {
+            Some(gamepad) => {
+                if gamepad.name.get_ref().trim().is_empty() {
+                    return Err(error(
+                        gamepad.name.span(),
+                        "gamepad name is empty".to_string(),
+                    ));
+                }
+                Some(Gamepad {
+                    name: gamepad.name.into_inner(),
+                    remap: gamepad.remap,
+                })
+            }
+            None => None,
+        };
+
+        let mut axes = HashMap::new();
+        for (analog, axis) in raw.axis {
+            axes.insert(analog, parse_axis(src, axis)?);
+        }
+
+        let mut names: Vec<String> = Vec::new();
+        for layer in raw.layer.iter() {
+            let name = layer.name.get_ref();
+            if names.contains(name) {
+                return Err(error(
+                    layer.name.span(),
+                    format!("layer {:?} is defined more than once", name),
+                ));
+            }
+            names.push(name.clone());
+        }
+
+        let analog_threshold = *raw.thresholds.analog.get_ref();
+        let base = RawLayer {
+            name: Spanned::new(0..0, String::new()),
+            bind: raw.bind,
+            analog: raw.analog,
+            radial: raw.radial,
+            pointer: raw.pointer,
+            scroll: raw.scroll,
+        };
+        let base = parse_layer(src, &names, analog_threshold, base)?;
+        let mut layers = Vec::new();
+        for layer in raw.layer {
+            layers.push(parse_layer(src, &names, analog_threshold, layer)?);
+        }
+
+        Ok(Self {
+            thresholds: Thresholds {
+                radial: *raw.thresholds.radial.get_ref(),
+            },
+            input,
+            kill_switch,
+            gamepad,
+            axes,
+            base,
+            layers,
+        })
+    }
+
+    // every key the profile can press, for registering on the virtual device
+    pub fn keys(&self) -> Vec<Key> {
+        let mut keys = Vec::new();
+        for layer in std::iter::once(&self.base).chain(self.layers.iter()) {
+            keys.extend(layer.buttons.iter().flat_map(|b| b.action.keys()));
+            for bind in layer.analogs.iter() {
+                keys.extend(bind.action.keys());
+                keys.extend(bind.full.iter().flat_map(|f| f.action.keys()));
+            }
+            keys.extend(layer.radials.iter().flat_map(|r| r.keys()));
+        }
+        keys
+    }
+}
+
+fn parse_layer(
+    src: &str,
+    names: &[String],
+    analog_threshold: f32,
+    layer: RawLayer,
+) -> Result<Layer, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let mut buttons: Vec<ButtonBind> = Vec::new();
+    for bind in layer.bind {
+        let button = *bind.button.get_ref();
+        if buttons.iter().any(|b| b.button == button) {
+            return Err(error(
+                bind.button.span(),
+                format!("{:?} is bound more than once", button),
+            ));
+        }
+        validate_action(bind.action.get_ref(), names).map_err(|m| error(bind.action.span(), m))?;
+        buttons.push(ButtonBind {
+            button,
+            action: bind.action.into_inner(),
+        });
+    }
+
+    let mut analogs: Vec<AnalogBind> = Vec::new();
+    for bind in layer.analog {
+        let analog = *bind.analog.get_ref();
+        let direction = *bind.direction.get_ref();
+        if analogs
+            .iter()
+            .any(|a| a.analog == analog && a.direction == direction)
+        {
+            return Err(error(
+                bind.analog.span(),
+                format!("{:?} is bound more than once", analog),
+            ));
+        }
+        // triggers rest at 0 and never go below it
+        let centered = !matches!(analog, CommonAnalog::LeftLever | CommonAnalog::RightLever);
+        if direction == Direction::Negative && !centered {
+            return Err(error(
+                bind.direction.span(),
+                format!("{:?} has no negative direction", analog),
+            ));
+        }
+        let default = Spanned::new(0..0, analog_threshold);
+        let threshold = parse_threshold(
+            src,
+            bind.press.as_ref().unwrap_or(&default),
+            bind.release.as_ref(),
+        )?;
+        validate_action(bind.action.get_ref(), names).map_err(|m| error(bind.action.span(), m))?;
+        let full = match bind.full {
+            Some(full) => {
+                let full_threshold = parse_threshold(src, &full.press, full.release.as_ref())?;
+                if full_threshold.press <= threshold.press {
+                    return Err(error(
+                        full.press.span(),
+                        format!(
+                            "full pull press {} must be above the first stage's {}",
+                            full_threshold.press, threshold.press
+                        ),
+                    ));
+                }
+                validate_action(full.action.get_ref(), names)
+                    .map_err(|m| error(full.action.span(), m))?;
+                Some(FullPull {
+                    threshold: full_threshold,
+                    action: full.action.into_inner(),
+                })
+            }
+            None => None,
+        };
+        analogs.push(AnalogBind {
+            analog,
+            direction,
+            threshold,
+            action: bind.action.into_inner(),
+            full,
+        });
+    }
+
+    let mut radials: Vec<Radial> = Vec::new();
+    for radial in layer.radial {
+        let source = *radial.source.get_ref();
+        if radials.iter().any(|r| r.source == source) {
+            // a defaulted source has no span, point at the menu's keys instead
+            let span = match radial.source.span() {
+                span if span.is_empty() => radial.keys.span(),
+                span => span,
+            };
+            return Err(error(
+                span,
+                format!("{:?} already drives a radial menu in this layer", source),
+            ));
+        }
+        radials.push(parse_radial(src, radial)?);
+    }
+
+    let pointer = match layer.pointer {
+        Some(pointer) => Some(parse_pointer(src, pointer)?),
+        None => None,
+    };
+    let scroll = match layer.scroll {
+        Some(scroll) => Some(parse_scroll(src, scroll)?),
+        None => None,
+    };
+
+    Ok(Layer {
+        name: layer.name.into_inner(),
+        buttons,
+        analogs,
+        radials,
+        pointer,
+        scroll,
+    })
+}
+
+fn parse_pointer(src: &str, pointer: RawPointer) -> Result<Pointer, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let speed = *pointer.speed.get_ref();
+    if speed <= 0.0 {
+        return Err(error(
+            pointer.speed.span(),
+            format!("pointer speed {} must be above 0.0", speed),
+        ));
+    }
+    let precision_speed = *pointer.precision_speed.get_ref();
+    if precision_speed <= 0.0 {
+        return Err(error(
+            pointer.precision_speed.span(),
+            format!("precision_speed {} must be above 0.0", precision_speed),
+        ));
+    }
+    let acceleration_span = pointer.acceleration.span();
+    let acceleration = pointer.acceleration.into_inner();
+    validate_curve(&acceleration).map_err(|m| error(acceleration_span, m))?;
+
+    Ok(Pointer {
+        source: pointer.source,
+        speed,
+        acceleration,
+        precision: pointer.precision,
+        precision_speed,
+    })
+}
+
+// release defaults to the press threshold, for no hysteresis
+fn parse_threshold(
+    src: &str,
+    press: &Spanned<f32>,
+    release: Option<&Spanned<f32>>,
+) -> Result<Threshold, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let value = *press.get_ref();
+    if !(0.0..1.0).contains(&value) {
+        return Err(error(
+            press.span(),
+            format!("press threshold {} is outside 0.0..1.0", value),
+        ));
+    }
+    let release_value = release.map(|r| *r.get_ref()).unwrap_or(value);
+    if !(0.0..=value).contains(&release_value) {
+        return Err(error(
+            release.unwrap().span(),
+            format!(
+                "release threshold {} must be within 0.0..{}, the press threshold",
+                release_value, value
+            ),
+        ));
+    }
+    Ok(Threshold {
+        press: value,
+        release: release_value,
+    })
+}
+
+fn parse_axis(src: &str, axis: RawAxis) -> Result<AxisSettings, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let deadzone = *axis.deadzone.get_ref();
+    if !(0.0..1.0).contains(&deadzone) {
+        return Err(error(
+            axis.deadzone.span(),
+            format!("deadzone {} is outside 0.0..1.0", deadzone),
+        ));
+    }
+    let outer = *axis.outer.get_ref();
+    if outer <= deadzone || outer > 1.0 {
+        return Err(error(
+            axis.outer.span(),
+            format!("outer {} must be above the deadzone and at most 1.0", outer),
+        ));
+    }
+    let anti_deadzone = *axis.anti_deadzone.get_ref();
+    if !(0.0..1.0).contains(&anti_deadzone) {
+        return Err(error(
+            axis.anti_deadzone.span(),
+            format!("anti_deadzone {} is outside 0.0..1.0", anti_deadzone),
+        ));
+    }
+    let curve_span = axis.curve.span();
+    let curve = axis.curve.into_inner();
+    validate_curve(&curve).map_err(|m| error(curve_span, m))?;
+
+    Ok(AxisSettings {
+        deadzone,
+        outer,
+        anti_deadzone,
+        curve,
+        invert: axis.invert,
+        radial: axis.radial,
+    })
+}
+
+fn parse_scroll(src: &str, scroll: Spanned<RawScroll>) -> Result<Scroll, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let span = scroll.span();
+    let scroll = scroll.into_inner();
+    if scroll.source.is_none() && scroll.up.is_none() && scroll.down.is_none() {
+        return Err(error(span, "scroll needs a source, up or down".to_string()));
+    }
+    let speed = *scroll.speed.get_ref();
+    if speed <= 0.0 {
+        return Err(error(
+            scroll.speed.span(),
+            format!("scroll speed {} must be above 0.0", speed),
+        ));
+    }
+
+    Ok(Scroll {
+        source: scroll.source,
+        up: scroll.up,
+        down: scroll.down,
+        speed,
+        notch: scroll.notch,
+    })
+}
+
+fn validate_curve(curve: &Curve) -> Result<(), String> {
+    match curve {
+        Curve::Linear => (),
+        Curve::Power(exponent) if *exponent <= 0.0 => {
+            return Err(format!(
+                "power curve exponent {} must be above 0.0",
+                exponent
+            ))
+        }
+        Curve::Power(_) => (),
+        Curve::Spline(points) => {
+            if points.len() < 2 {
+                return Err("spline curve needs at least 2 points".to_string());
+            }
+            if points.iter().flatten().any(|v| !(0.0..=1.0).contains(v)) {
+                return Err("spline curve points must be within 0.0..1.0".to_string());
+            }
+            if points.windows(2).any(|w| w[1][0] <= w[0][0]) {
+                return Err("spline curve points must be in ascending order".to_string());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_radial(src: &str, radial: RawRadial) -> Result<Radial, ProfileError> {
+    let error = |span: Range<usize>, message: String| ProfileError {
+        line: Some(line_of(src, span)),
+        message,
+    };
+
+    let keys_span = radial.keys.span();
+    let raw_keys = radial.keys.into_inner();
+    if raw_keys.is_empty() {
+        return Err(error(keys_span, "radial menu has no keys".to_string()));
+    }
+    let mut keys = Vec::new();
+    for key in raw_keys {
+        let mut chars = key.label.get_ref().chars();
+        let label = match (chars.next(), chars.next()) {
+            (Some(ch), None) => ch,
+            _ => {
+                return Err(error(
+                    key.label.span(),
+                    format!(
+                        "radial label {:?} must be a single character",
+                        key.label.get_ref()
+                    ),
+                ))
+            }
+        };
+        let weight = *key.weight.get_ref();
+        if weight <= 0.0 {
+            return Err(error(
+                key.weight.span(),
+                format!("radial weight {} must be above 0.0", weight),
+            ));
+        }
+        let target = match (key.key, key.menu) {
+            (Some(key), None) => RadialTarget::Key(key),
+            (None, Some(menu)) => RadialTarget::Menu(parse_radial(src, menu)?),
+            _ => {
+                return Err(error(
+                    key.label.span(),
+                    format!(
+                        "radial item {:?} needs either a key or a menu",
+                        key.label.get_ref()
+                    ),
+                ))
+            }
+        };
+        keys.push(RadialKey {
+            label,
+            target,
+            weight,
+        });
+    }
+
+    let hysteresis = *radial.hysteresis.get_ref();
+    if !(0.0..90.0).contains(&hysteresis) {
+        return Err(error(
+            radial.hysteresis.span(),
+            format!(
+                "radial hysteresis {} must be between 0 and 90 degrees",
+                hysteresis
+            ),
+        ));
+    }
+
+    let source = radial.source.into_inner();
+    let defaults = source.default_overlay();
+    if let Some(size) = &radial.overlay.size {
+        if *size.get_ref() <= 0 {
+            return Err(error(
+                size.span(),
+                format!("overlay size {} must be above 0", size.get_ref()),
+            ));
+        }
+    }
+    let overlay = Overlay {
+        anchor: radial.overlay.anchor.unwrap_or(defaults.anchor),
+        margin: radial.overlay.margin.unwrap_or(defaults.margin),
+        size: radial
+            .overlay
+            .size
+            .map(Spanned::into_inner)
+            .unwrap_or(defaults.size),
+        theme: Theme {
+            ring: radial.theme.ring.unwrap_or(defaults.theme.ring),
+            marker: radial.theme.marker.unwrap_or(defaults.theme.marker),
+            text: radial.theme.text.unwrap_or(defaults.theme.text),
+            selected: radial.theme.selected.unwrap_or(defaults.theme.selected),
+        },
+    };
+
+    let text_entry = match radial.text_entry {
+        Some(text_entry) => {
+            let suggestions = *text_entry.suggestions.get_ref();
+            if suggestions == 0 {
+                return Err(error(
+                    text_entry.suggestions.span(),
+                    "text entry needs at least 1 suggestion".to_string(),
+                ));
+            }
+            Some(TextEntry {
+                dictionary: text_entry.dictionary,
+                select: text_entry.select,
+                accept: text_entry.accept,
+                suggestions,
+            })
+        }
+        None => None,
+    };
+
+    Ok(Radial {
+        source,
+        overlay,
+        keys,
+        start_angle: radial.start_angle,
+        clockwise: radial.clockwise,
+        hysteresis,
+        dwell_ms: radial.dwell_ms,
+        commit_on_release: radial.commit_on_release,
+        back: radial.back.or(source.default_back()),
+        timeout_ms: radial.timeout_ms,
+        text_entry,
+    })
+}
+
+pub fn radial_labels(radial: &Radial) -> String {
+    radial.keys.iter().map(|r| r.label).collect()
+}
+
+fn validate_action(action: &Action, layers: &[String]) -> Result<(), String> {
+    match action {
+        Action::Chord(keys) if keys.is_empty() => Err("chord has no keys".to_string()),
+        Action::Text(text) => match text.chars().find(|ch| char_to_key(*ch).is_none()) {
+            Some(ch) => Err(format!("{:?} can not be typed", ch)),
+            None => Ok(()),
+        },
+        Action::MouseButton(key) if !is_mouse_button(*key) => {
+            Err(format!("{:?} is not a mouse button", key))
+        }
+        Action::TapHold { tap, hold, .. } => {
+            for inner in [tap, hold] {
+                if let Action::TapHold { .. } = **inner {
+                    return Err("tap_hold can not contain another tap_hold".to_string());
+                }
+                validate_action(inner, layers)?;
+            }
+            Ok(())
+        }
+        Action::Layer(name) | Action::ToggleLayer(name) if !layers.contains(name) => {
+            Err(format!("there is no layer named {:?}", name))
+        }
+        _ => Ok(()),
+    }
+}
+
+pub fn line_of(src: &str, span: Range<usize>) -> usize {
+    let end = span.start.min(src.len());
+    src[..end].matches('\n').count() + 1
+}
+
+// $XDG_CONFIG_HOME/padmixer, falling back to ~/.config/padmixer
+pub fn config_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("padmixer"))
+}
+
+pub fn profile_path() -> Option<PathBuf> {
+    Some(config_dir()?.join(PROFILE_FILE))
+}
+
+// loads the user profile if there is one, otherwise the bundled default.
+// a broken user profile is reported and the default is used instead.
+pub fn load(default_src: &str) -> Profile {
+    if let Some(path) = profile_path() {
+        match std::fs::read_to_string(&path) {
+            Ok(src) => match Profile::parse(&src) {
+                Ok(profile) => {
+                    println!("loaded profile {}", path.display());
+                    return profile;
+                }
+                Err(e) => println!("{}: {}", path.display(), e),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+            Err(e) => println!("{}: {}", path.display(), e),
+        }
+    }
+    println!("using default profile");
+    Profile::parse(default_src).expect("default profile parse")
+}
+
+// a profile other than the default, for pads assigned to it. errors are
+// reported and give none, so the pad falls back to the default.
+pub fn load_file(path: &Path) -> Option<Profile> {
+    let src = match std::fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match Profile::parse(&src) {
+        Ok(profile) => {
+            println!("loaded profile {}", path.display());
+            Some(profile)
+        }
+        Err(e) => {
+            println!("{}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+// polls the profile file and sends every change that parses.
+// profiles with errors are reported and skipped, so the last good one stays active.
+pub fn watch(path: PathBuf) -> async_channel::Receiver<Profile> {
+    let (tx, rx) = async_channel::unbounded();
+    thread::spawn(move || {
+        let modified = |path: &PathBuf| -> Option<SystemTime> {
+            std::fs::metadata(path).and_then(|m| m.modified()).ok()
+        };
+        let mut last = modified(&path);
+        loop {
+            thread::sleep(WATCH_INTERVAL);
+            let current = modified(&path);
+            if current == last {
+                continue;
+            }
+            last = current;
+            if current.is_none() {
+                println!("{}: removed, keeping current profile", path.display());
+                continue;
+            }
+            let src = match std::fs::read_to_string(&path) {
+                Ok(src) => src,
+                Err(e) => {
+                    println!("{}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            match Profile::parse(&src) {
+                Ok(profile) => {
+                    println!("reloaded profile {}", path.display());
+                    if tx.send_blocking(profile).is_err() {
+                        return;
+                    }
+                }
+                Err(e) => println!("{}: {}", path.display(), e),
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the line and message a profile is turned down with
+    fn rejected(src: &str) -> (Option<usize>, String) {
+        let Err(e) = Profile::parse(src) else {
+            panic!("expected the profile to be rejected:\n{}", src);
+        };
+        (e.line, e.message)
+    }
+
+    #[test]
+    fn the_default_profile_parses() {
+        let profile = Profile::parse(include_str!("../res/profile.toml")).unwrap();
+        assert!(!profile.keys().is_empty());
+    }
+
+    #[test]
+    fn toml_errors_point_at_their_line() {
+        let (line, message) =
+            rejected("[[bind]]\nbutton = \"FaceSouth\"\nactoin = { key = \"KEY_A\" }\n");
+        assert_eq!(line, Some(3));
+        assert!(message.starts_with("unknown field `actoin`"), "{}", message);
+        let (line, _) = rejected("[[bind]]\nbutton = \"FaceNowhere\"\n");
+        assert_eq!(line, Some(2));
+        // the shoulders are LegacyLT and LegacyRT
+        let (line, message) = rejected("[kill_switch]\nbuttons = [\"Start\", \"LeftShoulder\"]\n");
+        assert_eq!(line, Some(2));
+        assert!(
+            message.starts_with("unknown variant `LeftShoulder`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn binds_are_checked() {
+        let src = "[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_A\" }\n\n[[bind]]\nbutton = \"FaceSouth\"\naction = { key = \"KEY_B\" }\n";
+        assert_eq!(
+            rejected(src),
+            (Some(6), "FaceSouth is bound more than once".to_string())
+        );
+        let src = "[[bind]]\nbutton = \"FaceSouth\"\naction = { text = \"né\" }\n";
+        assert_eq!(rejected(src), (Some(3), "'é' can not be typed".to_string()));
+        let src = "[[bind]]\nbutton = \"FaceSouth\"\naction = { toggle_layer = \"nav\" }\n";
+        assert_eq!(
+            rejected(src),
+            (Some(3), "there is no layer named \"nav\"".to_string())
+        );
+        // binds inside a layer too
+        let src = "[[layer]]\nname = \"nav\"\n\n[[layer.bind]]\nbutton = \"FaceSouth\"\naction = { chord = [] }\n";
+        assert_eq!(rejected(src), (Some(6), "chord has no keys".to_string()));
+    }
+
+    #[test]
+    fn thresholds_and_axes_are_checked() {
+        assert_eq!(
+            rejected("[thresholds]\nradial = 0.5\nanalog = 1.5\n"),
+            (Some(3), "threshold 1.5 is outside 0.0..1.0".to_string())
+        );
+        assert_eq!(
+            rejected("[axis.LeftStickX]\ndeadzone = 0.3\nouter = 0.2\n"),
+            (
+                Some(3),
+                "outer 0.2 must be above the deadzone and at most 1.0".to_string()
+            )
+        );
+        assert_eq!(
+            rejected(
+                "[axis.LeftLever]\ncurve = { spline = [[0.0, 0.0], [0.6, 0.5], [0.4, 1.0]] }\n"
+            ),
+            (
+                Some(2),
+                "spline curve points must be in ascending order".to_string()
+            )
+        );
+        let src = "[[analog]]\nanalog = \"LeftLever\"\npress = 0.3\nrelease = 0.4\naction = { key = \"KEY_A\" }\n";
+        assert_eq!(
+            rejected(src),
+            (
+                Some(4),
+                "release threshold 0.4 must be within 0.0..0.3, the press threshold".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn radials_pointer_and_scroll_are_checked() {
+        assert_eq!(
+            rejected("[[radial]]\nkeys = []\n"),
+            (Some(2), "radial menu has no keys".to_string())
+        );
+        let src = "[[radial]]\nsource = \"DPad\"\nkeys = [{ label = \"a\", key = \"KEY_A\" }]\n\n[[radial]]\nsource = \"DPad\"\nkeys = [{ label = \"b\", key = \"KEY_B\" }]\n";
+        assert_eq!(
+            rejected(src),
+            (
+                Some(6),
+                "DPad already drives a radial menu in this layer".to_string()
+            )
+        );
+        assert_eq!(
+            rejected("[pointer]\nsource = \"LeftStick\"\nspeed = 0\n"),
+            (Some(3), "pointer speed 0 must be above 0.0".to_string())
+        );
+        assert_eq!(
+            rejected("\n[scroll]\nspeed = 10\n"),
+            (Some(2), "scroll needs a source, up or down".to_string())
+        );
+    }
+}
@@ -0,0 +1,824 @@
+use std::f32::consts::TAU;
+use std::time::{Duration, Instant};
+
+use evdev::{EventType, InputEvent, Key};
+use serde::Deserialize;
+
+use crate::output::OutputSink;
+use crate::predict::Predictor;
+use crate::profile::{radial_labels, Anchor, Overlay, Radial, RadialTarget, Theme};
+use crate::types::{BasicControllerState, CommonAnalog, CommonButton, ListItemData, ValueStore};
+
+// degrees past a sector edge the stick must travel before the selection moves on
+pub const DEFAULT_HYSTERESIS: f32 = 8.0;
+// idle time in a submenu before going back to the top ring
+pub const DEFAULT_MENU_TIMEOUT_MS: u64 = 3000;
+// overlay window size and distance from the screen edges, in pixels
+const DEFAULT_OVERLAY_SIZE: i32 = 380;
+const DEFAULT_OVERLAY_MARGIN: i32 = 200;
+
+// what points into a radial menu. each source drives its own menu and overlay.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
+pub enum RadialSource {
+    RightStick,
+    LeftStick,
+    // the four d-pad buttons (or d-pad axes) read as an 8-way stick
+    DPad,
+}
+
+impl RadialSource {
+    pub const ALL: [RadialSource; 3] = [Self::RightStick, Self::LeftStick, Self::DPad];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::RightStick => "right",
+            Self::LeftStick => "left",
+            Self::DPad => "dpad",
+        }
+    }
+
+    // ValueStore key for one of this source's overlay values, on the pad in `pad`'s slot
+    pub fn store_key(self, pad: usize, value: &str) -> String {
+        format!("pad{}_radial_{}_{}", pad, self.name(), value)
+    }
+
+    // each source gets its own screen corner unless the profile says otherwise
+    pub fn default_overlay(self) -> Overlay {
+        let anchor = match self {
+            Self::RightStick => Anchor::BottomRight,
+            Self::LeftStick => Anchor::BottomLeft,
+            Self::DPad => Anchor::TopLeft,
+        };
+        Overlay {
+            anchor,
+            margin: [DEFAULT_OVERLAY_MARGIN; 2],
+            size: DEFAULT_OVERLAY_SIZE,
+            theme: Theme::default(),
+        }
+    }
+
+    // clicking the stick backs out of a submenu. the d-pad has nothing spare.
+    pub fn default_back(self) -> Option<CommonButton> {
+        match self {
+            Self::RightStick => Some(CommonButton::RightStickPress),
+            Self::LeftStick => Some(CommonButton::LeftStickPress),
+            Self::DPad => None,
+        }
+    }
+
+    // analogs and buttons `position` reads
+    pub fn analogs(self) -> &'static [CommonAnalog] {
+        match self {
+            Self::RightStick => &[CommonAnalog::RightStickX, CommonAnalog::RightStickY],
+            Self::LeftStick => &[CommonAnalog::LeftStickX, CommonAnalog::LeftStickY],
+            Self::DPad => &[CommonAnalog::DPadX, CommonAnalog::DPadY],
+        }
+    }
+
+    pub fn buttons(self) -> &'static [CommonButton] {
+        match self {
+            Self::RightStick | Self::LeftStick => &[],
+            Self::DPad => &[
+                CommonButton::DPadNorth,
+                CommonButton::DPadSouth,
+                CommonButton::DPadWest,
+                CommonButton::DPadEast,
+            ],
+        }
+    }
+
+    // x right and y up, like the sticks
+    pub fn position(self, state: &BasicControllerState) -> (f32, f32) {
+        let analog = |analog| state.analog_state_by_type(analog).value;
+        let button = |button| state.button_state_by_type(button).value;
+        match self {
+            Self::RightStick => (
+                analog(CommonAnalog::RightStickX),
+                analog(CommonAnalog::RightStickY),
+            ),
+            Self::LeftStick => (
+                analog(CommonAnalog::LeftStickX),
+                analog(CommonAnalog::LeftStickY),
+            ),
+            Self::DPad => (
+                (button(CommonButton::DPadEast) - button(CommonButton::DPadWest)
+                    + analog(CommonAnalog::DPadX))
+                .clamp(-1.0, 1.0),
+                (button(CommonButton::DPadNorth) - button(CommonButton::DPadSouth)
+                    + analog(CommonAnalog::DPadY))
+                .clamp(-1.0, 1.0),
+            ),
+        }
+    }
+}
+
+// geometry of a radial menu. the input loop selects with it and the
+// RadialMenu widget draws with it, so labels and hit sectors always agree.
+// angles are radians, counter-clockwise from the right with y pointing up.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RadialLayout {
+    // share of the circle per item, normalized to sum to TAU
+    sectors: Vec<f32>,
+    // center of the first item
+    start: f32,
+    clockwise: bool,
+    // stick magnitude below which nothing is selected
+    pub threshold: f32,
+}
+
+impl Default for RadialLayout {
+    fn default() -> Self {
+        Self::even(0)
+    }
+}
+
+impl RadialLayout {
+    pub fn new(weights: &[f32], start_degrees: f32, clockwise: bool, threshold: f32) -> Self {
+        let total: f32 = weights.iter().sum();
+        Self {
+            sectors: weights.iter().map(|w| w / total * TAU).collect(),
+            start: start_degrees.to_radians(),
+            clockwise,
+            threshold,
+        }
+    }
+
+    // equal sectors starting at the right, counter-clockwise
+    pub fn even(count: usize) -> Self {
+        Self::new(&vec![1.0; count], 0.0, false, 0.5)
+    }
+
+    pub fn from_radial(radial: &Radial, threshold: f32) -> Self {
+        let weights: Vec<f32> = radial.keys.iter().map(|k| k.weight).collect();
+        Self::new(&weights, radial.start_angle, radial.clockwise, threshold)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sectors.len()
+    }
+
+    // sectors laid out like `even`, whatever the threshold
+    pub fn is_even(&self) -> bool {
+        let even = Self::even(self.len());
+        self.sectors == even.sectors && self.start == even.start && self.clockwise == even.clockwise
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sectors.is_empty()
+    }
+
+    fn direction(&self) -> f32 {
+        if self.clockwise {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    // distance along the ring from the first sector's leading edge to where sector `index` begins
+    fn offset(&self, index: usize) -> f32 {
+        self.sectors[..index].iter().sum()
+    }
+
+    fn first_half(&self) -> f32 {
+        self.sectors.first().map(|s| s / 2.0).unwrap_or(0.0)
+    }
+
+    // angle of the middle of a sector, where its label goes
+    pub fn center(&self, index: usize) -> f32 {
+        let along = self.offset(index) + self.sectors[index] / 2.0 - self.first_half();
+        self.start + self.direction() * along
+    }
+
+    // angle of the edge between sector `index` and the one before it
+    pub fn boundary(&self, index: usize) -> f32 {
+        let along = self.offset(index) - self.first_half();
+        self.start + self.direction() * along
+    }
+
+    // where the stick points along the ring, 0..TAU from the first sector's leading edge
+    fn along(&self, x: f32, y: f32) -> f32 {
+        let angle = f32::atan2(y, x);
+        ((angle - self.start) * self.direction() + self.first_half()).rem_euclid(TAU)
+    }
+
+    fn sector_at(&self, along: f32) -> usize {
+        let mut edge = 0.0;
+        for (index, sector) in self.sectors.iter().enumerate() {
+            edge += sector;
+            if along < edge {
+                return index;
+            }
+        }
+        self.sectors.len() - 1
+    }
+
+    pub fn select(&self, x: f32, y: f32) -> Option<usize> {
+        if self.is_empty() || f32::hypot(x, y) < self.threshold {
+            return None;
+        }
+        Some(self.sector_at(self.along(x, y)))
+    }
+
+    // whether the stick points into sector `index` widened by `margin` radians on each side
+    pub fn contains(&self, index: usize, x: f32, y: f32, margin: f32) -> bool {
+        let middle = self.offset(index) + self.sectors[index] / 2.0;
+        let distance = (self.along(x, y) - middle).rem_euclid(TAU);
+        distance.min(TAU - distance) <= self.sectors[index] / 2.0 + margin
+    }
+}
+
+// turns stick positions into radial key choices without flapping between neighbours.
+// a selection sticks until the stick is `hysteresis` past its sector, and a new
+// sector must be pointed at for `dwell` before it replaces the old one.
+pub struct RadialSelector {
+    hysteresis: f32,
+    dwell: Duration,
+    commit_on_release: bool,
+    selected: Option<usize>,
+    pending: Option<(usize, Instant)>,
+}
+
+impl RadialSelector {
+    pub fn new(radial: &Radial) -> Self {
+        Self {
+            hysteresis: radial.hysteresis.to_radians(),
+            dwell: Duration::from_millis(radial.dwell_ms),
+            commit_on_release: radial.commit_on_release,
+            selected: None,
+            pending: None,
+        }
+    }
+
+    // the sector the stick has settled on, highlighted in the overlay
+    pub fn selected(&self) -> Option<usize> {
+        self.selected
+    }
+
+    // the key that should be held down right now
+    pub fn held(&self) -> Option<usize> {
+        if self.commit_on_release {
+            None
+        } else {
+            self.selected
+        }
+    }
+
+    // feed the current stick position. returns the sector that was
+    // selected when the stick came back to center.
+    pub fn update(&mut self, layout: &RadialLayout, x: f32, y: f32, now: Instant) -> Option<usize> {
+        let Some(mut index) = layout.select(x, y) else {
+            self.pending = None;
+            return self.selected.take();
+        };
+
+        if let Some(selected) = self.selected {
+            if layout.contains(selected, x, y, self.hysteresis) {
+                index = selected;
+            }
+        }
+        if Some(index) == self.selected {
+            self.pending = None;
+            return None;
+        }
+
+        let since = match self.pending {
+            Some((pending, since)) if pending == index => since,
+            _ => now,
+        };
+        if now.duration_since(since) >= self.dwell {
+            self.selected = Some(index);
+            self.pending = None;
+        } else {
+            self.pending = Some((index, since));
+        }
+        None
+    }
+}
+
+// walks a radial menu tree. submenu items open their ring when the stick
+// returns to center, and picking a key inside a submenu goes back to the top.
+pub struct RadialNavigator {
+    root: Radial,
+    threshold: f32,
+    // indices of the opened submenu items, from the top ring down
+    path: Vec<usize>,
+    layout: RadialLayout,
+    selector: RadialSelector,
+    // none until the first update, so the timeout runs on frame time only
+    idle_since: Option<Instant>,
+    back_down: bool,
+}
+
+impl RadialNavigator {
+    pub fn new(root: Radial, threshold: f32) -> Self {
+        let layout = RadialLayout::from_radial(&root, threshold);
+        let selector = RadialSelector::new(&root);
+        Self {
+            root,
+            threshold,
+            path: Vec::new(),
+            layout,
+            selector,
+            idle_since: None,
+            back_down: false,
+        }
+    }
+
+    pub fn root(&self) -> &Radial {
+        &self.root
+    }
+
+    // the ring currently shown
+    pub fn ring(&self) -> &Radial {
+        let mut ring = &self.root;
+        for index in self.path.iter() {
+            if let RadialTarget::Menu(menu) = &ring.keys[*index].target {
+                ring = menu;
+            }
+        }
+        ring
+    }
+
+    pub fn layout(&self) -> &RadialLayout {
+        &self.layout
+    }
+
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    // labels of the opened submenu items, e.g. "a > e"
+    pub fn breadcrumbs(&self) -> String {
+        let mut ring = &self.root;
+        let mut labels = Vec::new();
+        for index in self.path.iter() {
+            let item = &ring.keys[*index];
+            labels.push(item.label.to_string());
+            if let RadialTarget::Menu(menu) = &item.target {
+                ring = menu;
+            }
+        }
+        labels.join(" > ")
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.selector.selected()
+    }
+
+    // the key that should be held down right now
+    pub fn held(&self) -> Option<Key> {
+        let index = self.selector.held()?;
+        match self.ring().keys[index].target {
+            RadialTarget::Key(key) => Some(key),
+            RadialTarget::Menu(_) => None,
+        }
+    }
+
+    fn open(&mut self, path: Vec<usize>) {
+        self.path = path;
+        self.layout = RadialLayout::from_radial(self.ring(), self.threshold);
+        self.selector = RadialSelector::new(self.ring());
+    }
+
+    // feed the stick position and whether the back button is down.
+    // returns a key to type once, for rings that commit on release.
+    pub fn update(&mut self, x: f32, y: f32, back_down: bool, now: Instant) -> Option<Key> {
+        if back_down && !self.back_down && !self.path.is_empty() {
+            let mut path = self.path.clone();
+            path.pop();
+            self.open(path);
+        }
+        self.back_down = back_down;
+
+        if self.layout.select(x, y).is_some() || self.idle_since.is_none() {
+            self.idle_since = Some(now);
+        }
+
+        let released = self.selector.update(&self.layout, x, y, now);
+        match released.map(|index| (index, &self.ring().keys[index].target)) {
+            Some((index, RadialTarget::Menu(_))) => {
+                let mut path = self.path.clone();
+                path.push(index);
+                self.open(path);
+                None
+            }
+            Some((_, RadialTarget::Key(key))) => {
+                let tapped = Some(*key).filter(|_| self.ring().commit_on_release);
+                if !self.path.is_empty() {
+                    self.open(Vec::new());
+                }
+                tapped
+            }
+            None => {
+                let timeout = Duration::from_millis(self.root.timeout_ms);
+                if !self.path.is_empty()
+                    && !timeout.is_zero()
+                    && self
+                        .idle_since
+                        .is_some_and(|since| now.duration_since(since) >= timeout)
+                {
+                    self.open(Vec::new());
+                }
+                None
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for RadialLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<RadialLayout {}>", self.sectors.len())
+    }
+}
+
+impl ListItemData for RadialLayout {}
+
+// one radial menu instance: the navigator for whatever menu its source currently
+// drives, the key it holds, and what was last sent to its overlay.
+pub struct RadialDriver {
+    source: RadialSource,
+    pad: usize,
+    nav: Option<RadialNavigator>,
+    held: Option<Key>,
+    // word completion, for menus with text entry
+    predictor: Option<Predictor>,
+    position: (f32, f32),
+    // none when the overlay needs the whole ring again
+    shown_path: Option<Vec<usize>>,
+    shown_selected: Option<usize>,
+    shown_position: (f32, f32),
+    shown_completions: (Vec<String>, Option<usize>),
+}
+
+impl RadialDriver {
+    // also puts the overlay's starting values in the store
+    pub fn new(source: RadialSource, pad: usize, store: &mut ValueStore) -> Self {
+        store.insert(&source.store_key(pad, "x"), 0.0);
+        store.insert(&source.store_key(pad, "y"), 0.0);
+        store.insert(&source.store_key(pad, "labels"), String::new());
+        store.insert(&source.store_key(pad, "layout"), RadialLayout::default());
+        store.insert(&source.store_key(pad, "selected"), -1);
+        store.insert(&source.store_key(pad, "breadcrumbs"), String::new());
+        store.insert(&source.store_key(pad, "overlay"), source.default_overlay());
+        store.insert(&source.store_key(pad, "completions"), String::new());
+        store.insert(&source.store_key(pad, "completion"), -1);
+        Self {
+            source,
+            pad,
+            nav: None,
+            held: None,
+            predictor: None,
+            position: (0.0, 0.0),
+            shown_path: None,
+            shown_selected: None,
+            shown_position: (0.0, 0.0),
+            shown_completions: (Vec::new(), None),
+        }
+    }
+
+    pub fn source(&self) -> RadialSource {
+        self.source
+    }
+
+    // follow the menu the active layers give this source, or a changed threshold
+    pub fn sync(&mut self, radial: Option<&Radial>, threshold: f32, out: &mut dyn OutputSink) {
+        let current = self.nav.as_ref().map(|nav| (nav.root(), nav.threshold));
+        if current == radial.map(|radial| (radial, threshold)) {
+            return;
+        }
+        if let Some(held) = self.held.take() {
+            out.send(&[InputEvent::new(EventType::KEY, held.code(), 0)]);
+        }
+        self.nav = radial.map(|radial| RadialNavigator::new(radial.clone(), threshold));
+        self.shown_path = None;
+
+        // the dictionary is only read again when the text entry settings change
+        let text_entry = radial.and_then(|radial| radial.text_entry.as_ref());
+        if self.predictor.as_ref().map(|p| p.config()) != text_entry {
+            self.predictor = text_entry.map(|config| Predictor::new(config.clone()));
+        }
+    }
+
+    // forget held keys that were already released some other way
+    pub fn reset(&mut self) {
+        self.held = None;
+        self.nav = None;
+        self.predictor = None;
+        self.shown_path = None;
+    }
+
+    pub fn update(&mut self, state: &BasicControllerState, now: Instant, out: &mut dyn OutputSink) {
+        self.position = self.source.position(state);
+        let Some(nav) = self.nav.as_mut() else {
+            return;
+        };
+        let (x, y) = self.position;
+        let back_down = nav.root().back.is_some_and(|back| {
+            state.button_state_by_type(back).value > crate::binding::BUTTON_PRESS
+        });
+
+        // the key whose sector the stick settled into, held or typed on release
+        let tapped = nav.update(x, y, back_down, now);
+        let selected = nav.held();
+        let pressed = selected.filter(|_| selected != self.held);
+        if selected != self.held {
+            if let Some(held) = self.held {
+                out.send(&[InputEvent::new(EventType::KEY, held.code(), 0)]);
+            }
+            if let Some(key) = selected {
+                out.send(&[InputEvent::new(EventType::KEY, key.code(), 1)]);
+            }
+            self.held = selected;
+        }
+        if let Some(key) = tapped {
+            out.send(&[
+                InputEvent::new(EventType::KEY, key.code(), 1),
+                InputEvent::new(EventType::KEY, key.code(), 0),
+            ]);
+        }
+
+        if let Some(predictor) = self.predictor.as_mut() {
+            if let Some(key) = pressed.or(tapped) {
+                predictor.typed(key);
+            }
+            let mut events = Vec::new();
+            predictor.update(state, &mut events);
+            out.send(&events);
+        }
+    }
+
+    fn set<T: ListItemData>(&self, store: &mut ValueStore, value: &str, v: T) {
+        store
+            .get(&self.source.store_key(self.pad, value))
+            .replace(Box::new(v), store);
+    }
+
+    // send what changed since the last frame to the overlay
+    pub fn publish(&mut self, store: &mut ValueStore) {
+        if self.position != self.shown_position {
+            self.set(store, "x", self.position.0);
+            self.set(store, "y", self.position.1);
+            self.shown_position = self.position;
+        }
+
+        // entering or leaving a submenu swaps the ring shown in the overlay
+        let path = self
+            .nav
+            .as_ref()
+            .map(|nav| nav.path().to_vec())
+            .unwrap_or_default();
+        let stale = self.shown_path.as_ref() != Some(&path);
+        if stale {
+            match &self.nav {
+                Some(nav) => {
+                    self.set(store, "labels", radial_labels(nav.ring()));
+                    self.set(store, "layout", nav.layout().clone());
+                    self.set(store, "breadcrumbs", nav.breadcrumbs());
+                    self.set(store, "overlay", nav.root().overlay.clone());
+                }
+                // an empty ring hides the overlay
+                None => {
+                    self.set(store, "labels", String::new());
+                    self.set(store, "layout", RadialLayout::default());
+                }
+            }
+            self.shown_path = Some(path);
+        }
+
+        let selected = self.nav.as_ref().and_then(|nav| nav.selected());
+        if stale || selected != self.shown_selected {
+            let index = selected.map(|index| index as i32).unwrap_or(-1);
+            self.set(store, "selected", index);
+            self.shown_selected = selected;
+        }
+
+        // completions for the word being typed, drawn below the ring
+        let completions = match &self.predictor {
+            Some(predictor) => (predictor.completions().to_vec(), predictor.highlighted()),
+            None => (Vec::new(), None),
+        };
+        if completions != self.shown_completions {
+            self.set(store, "completions", completions.0.join("\n"));
+            let index = completions.1.map(|index| index as i32).unwrap_or(-1);
+            self.set(store, "completion", index);
+            self.shown_completions = completions;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Profile;
+
+    // stick pushed all the way towards `degrees`, counter-clockwise from the right
+    fn point(degrees: f32) -> (f32, f32) {
+        let theta = degrees.to_radians();
+        (theta.cos(), theta.sin())
+    }
+
+    fn select(layout: &RadialLayout, degrees: f32) -> Option<usize> {
+        let (x, y) = point(degrees);
+        layout.select(x, y)
+    }
+
+    fn degrees(radians: f32) -> f32 {
+        (radians.to_degrees() * 1000.0).round() / 1000.0
+    }
+
+    // a four key ring with the given selector settings
+    fn ring(settings: &str) -> Radial {
+        let src = format!(
+            "[[radial]]\n{settings}\nkeys = [\n\
+             {{ label = \"a\", key = \"KEY_A\" }}, {{ label = \"b\", key = \"KEY_B\" }},\n\
+             {{ label = \"c\", key = \"KEY_C\" }}, {{ label = \"d\", key = \"KEY_D\" }},\n]\n"
+        );
+        Profile::parse(&src).unwrap().base.radials[0].clone()
+    }
+
+    struct Stick {
+        selector: RadialSelector,
+        layout: RadialLayout,
+        start: Instant,
+    }
+
+    impl Stick {
+        fn new(settings: &str) -> Self {
+            let radial = ring(settings);
+            Self {
+                selector: RadialSelector::new(&radial),
+                layout: RadialLayout::from_radial(&radial, 0.5),
+                start: Instant::now(),
+            }
+        }
+
+        fn at(&mut self, degrees: f32, ms: u64) -> Option<usize> {
+            let (x, y) = point(degrees);
+            let now = self.start + Duration::from_millis(ms);
+            self.selector.update(&self.layout, x, y, now)
+        }
+
+        fn center(&mut self, ms: u64) -> Option<usize> {
+            let now = self.start + Duration::from_millis(ms);
+            self.selector.update(&self.layout, 0.0, 0.0, now)
+        }
+    }
+
+    #[test]
+    fn even_sectors_are_centered_on_their_labels() {
+        let layout = RadialLayout::even(4);
+        let centers: Vec<f32> = (0..4).map(|i| degrees(layout.center(i))).collect();
+        assert_eq!(centers, [0.0, 90.0, 180.0, 270.0]);
+        assert_eq!(degrees(layout.boundary(0)), -45.0);
+        assert_eq!(degrees(layout.boundary(1)), 45.0);
+        assert_eq!(select(&layout, 44.0), Some(0));
+        assert_eq!(select(&layout, 46.0), Some(1));
+        assert_eq!(select(&layout, 134.0), Some(1));
+        assert_eq!(select(&layout, 136.0), Some(2));
+    }
+
+    #[test]
+    fn the_first_sector_wraps_around_the_right() {
+        let layout = RadialLayout::even(4);
+        assert_eq!(select(&layout, 0.0), Some(0));
+        assert_eq!(select(&layout, -44.0), Some(0));
+        assert_eq!(select(&layout, 316.0), Some(0));
+        assert_eq!(select(&layout, 314.0), Some(3));
+        assert_eq!(select(&layout, 359.9), Some(0));
+        // straight down, where atan2 jumps from -pi to pi
+        assert_eq!(select(&layout, 180.0), Some(2));
+        assert_eq!(select(&layout, -180.0), Some(2));
+    }
+
+    #[test]
+    fn weights_size_sectors_from_the_start_angle() {
+        // 180, 90 and 90 degrees, the first centered straight up
+        let layout = RadialLayout::new(&[2.0, 1.0, 1.0], 90.0, false, 0.5);
+        let centers: Vec<f32> = (0..3).map(|i| degrees(layout.center(i))).collect();
+        assert_eq!(centers, [90.0, 225.0, 315.0]);
+        let boundaries: Vec<f32> = (0..3).map(|i| degrees(layout.boundary(i))).collect();
+        assert_eq!(boundaries, [0.0, 180.0, 270.0]);
+        assert_eq!(select(&layout, 1.0), Some(0));
+        assert_eq!(select(&layout, 179.0), Some(0));
+        assert_eq!(select(&layout, 181.0), Some(1));
+        assert_eq!(select(&layout, 271.0), Some(2));
+        assert_eq!(select(&layout, -1.0), Some(2));
+    }
+
+    #[test]
+    fn clockwise_lays_keys_out_the_other_way() {
+        let counter = RadialLayout::new(&[1.0; 4], 90.0, false, 0.5);
+        let clockwise = RadialLayout::new(&[1.0; 4], 90.0, true, 0.5);
+        assert_eq!(degrees(counter.center(1)), 180.0);
+        assert_eq!(degrees(clockwise.center(1)), 0.0);
+        assert_eq!(degrees(clockwise.center(3)), -180.0);
+        assert_eq!(select(&counter, 90.0), Some(0));
+        assert_eq!(select(&clockwise, 90.0), Some(0));
+        assert_eq!(select(&counter, 0.0), Some(3));
+        assert_eq!(select(&clockwise, 0.0), Some(1));
+        // the edge between the first two keys
+        assert_eq!(select(&clockwise, 46.0), Some(0));
+        assert_eq!(select(&clockwise, 44.0), Some(1));
+    }
+
+    #[test]
+    fn nothing_is_selected_inside_the_threshold() {
+        let layout = RadialLayout::new(&[1.0; 4], 0.0, false, 0.5);
+        assert_eq!(layout.select(0.4, 0.0), None);
+        assert_eq!(layout.select(0.3, 0.3), None);
+        assert_eq!(layout.select(0.5, 0.0), Some(0));
+        assert_eq!(layout.select(0.0, -0.6), Some(3));
+        assert_eq!(RadialLayout::default().select(1.0, 0.0), None);
+    }
+
+    #[test]
+    fn only_the_sectors_decide_whether_a_layout_is_even() {
+        assert!(RadialLayout::even(4).is_even());
+        assert!(RadialLayout::new(&[1.0; 4], 0.0, false, 0.2).is_even());
+        assert!(!RadialLayout::new(&[1.0; 4], 45.0, false, 0.5).is_even());
+        assert!(!RadialLayout::new(&[1.0; 4], 0.0, true, 0.5).is_even());
+        assert!(!RadialLayout::new(&[2.0, 1.0, 1.0], 0.0, false, 0.5).is_even());
+    }
+
+    #[test]
+    fn a_selection_sticks_until_the_stick_is_past_the_hysteresis() {
+        let mut stick = Stick::new("hysteresis = 8");
+        stick.at(0.0, 0);
+        assert_eq!(stick.selector.selected(), Some(0));
+        // 5 degrees into the next sector
+        stick.at(50.0, 10);
+        assert_eq!(stick.selector.selected(), Some(0));
+        stick.at(54.0, 20);
+        assert_eq!(stick.selector.selected(), Some(1));
+        // and the same on the way back
+        stick.at(40.0, 30);
+        assert_eq!(stick.selector.selected(), Some(1));
+        stick.at(36.0, 40);
+        assert_eq!(stick.selector.selected(), Some(0));
+        // across the wrap around the right
+        stick.at(-52.0, 50);
+        assert_eq!(stick.selector.selected(), Some(0));
+        stick.at(-54.0, 60);
+        assert_eq!(stick.selector.selected(), Some(3));
+    }
+
+    #[test]
+    fn a_new_sector_is_selected_after_the_dwell() {
+        let mut stick = Stick::new("hysteresis = 0\ndwell_ms = 50");
+        stick.at(0.0, 0);
+        stick.at(0.0, 49);
+        assert_eq!(stick.selector.selected(), None);
+        stick.at(0.0, 50);
+        assert_eq!(stick.selector.selected(), Some(0));
+        // passing over a sector doesn't start its dwell over for the next
+        stick.at(90.0, 60);
+        stick.at(180.0, 100);
+        stick.at(180.0, 149);
+        assert_eq!(stick.selector.selected(), Some(0));
+        stick.at(180.0, 150);
+        assert_eq!(stick.selector.selected(), Some(2));
+        // letting go before the dwell keeps the old selection
+        stick.at(270.0, 160);
+        assert_eq!(stick.center(170), Some(2));
+        assert_eq!(stick.selector.selected(), None);
+    }
+
+    #[test]
+    fn the_selection_is_held_or_committed_on_release() {
+        let mut stick = Stick::new("hysteresis = 0");
+        assert_eq!(stick.at(90.0, 0), None);
+        assert_eq!(stick.selector.held(), Some(1));
+        assert_eq!(stick.center(10), Some(1));
+        assert_eq!(stick.selector.held(), None);
+
+        let mut stick = Stick::new("hysteresis = 0\ncommit_on_release = true");
+        assert_eq!(stick.at(90.0, 0), None);
+        assert_eq!(stick.selector.selected(), Some(1));
+        assert_eq!(stick.selector.held(), None);
+        stick.at(180.0, 10);
+        assert_eq!(stick.selector.held(), None);
+        assert_eq!(stick.center(20), Some(2));
+        assert_eq!(stick.selector.selected(), None);
+        assert_eq!(stick.center(30), None);
+    }
+
+    #[test]
+    fn submenu_timeout_runs_on_frame_time() {
+        let src = include_str!("../sessions/submenu.toml");
+        let root = Profile::parse(src).unwrap().base.radials[0].clone();
+        let mut nav = RadialNavigator::new(root, 0.5);
+        // frames far from the wall clock, like a replay's
+        let start = Instant::now() + Duration::from_secs(3600);
+        let at = |ms| start + Duration::from_millis(ms);
+        nav.update(0.0, 0.0, false, at(0));
+        nav.update(1.0, 0.0, false, at(10));
+        nav.update(0.0, 0.0, false, at(20));
+        assert_eq!(nav.path(), [0]);
+        // idle since the stick last pointed at something
+        nav.update(0.0, 0.0, false, at(309));
+        assert_eq!(nav.path(), [0]);
+        nav.update(0.0, 0.0, false, at(310));
+        assert!(nav.path().is_empty());
+    }
+}
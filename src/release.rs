@@ -0,0 +1,199 @@
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::mapper::Outputs;
+use crate::output::BoxedSink;
+
+// every output device and what it still holds, shared so whichever thread sees
+// padmixer going away can let go of it
+pub type SharedOutputs = Arc<Mutex<Outputs<BoxedSink>>>;
+
+static EXITING: AtomicBool = AtomicBool::new(false);
+
+// how long a panic waits for another thread to let go of the outputs
+const PANIC_LOCK_TIMEOUT: Duration = Duration::from_millis(100);
+
+// once set, the input loop sends nothing more
+pub fn exiting() -> bool {
+    EXITING.load(Ordering::SeqCst)
+}
+
+// lets go of everything once padmixer is on its way out.
+// the input loop checks `exiting` under the same lock, so nothing is pressed again after.
+pub fn release_for_exit(outputs: &SharedOutputs) {
+    release_once(&EXITING, outputs);
+}
+
+fn release_once(exiting: &AtomicBool, outputs: &SharedOutputs) {
+    if exiting.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let mut outputs = outputs.lock().unwrap_or_else(|e| e.into_inner());
+    outputs.release_all();
+}
+
+// releases before the default hook reports the panic.
+// anything still running afterwards carries on.
+pub fn install_panic_hook(outputs: SharedOutputs) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        release_on_panic(&EXITING, &outputs, PANIC_LOCK_TIMEOUT);
+        default_hook(info);
+    }));
+}
+
+// waits a little for a thread that is busy with the outputs. a panic in the
+// input loop itself holds the lock until it unwinds, and is left to `ReleaseOnDrop`.
+fn release_on_panic(exiting: &AtomicBool, outputs: &SharedOutputs, timeout: Duration) {
+    let deadline = Instant::now() + timeout;
+    let mut locked = loop {
+        match outputs.try_lock() {
+            Ok(outputs) => break outputs,
+            Err(TryLockError::Poisoned(e)) => break e.into_inner(),
+            Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(TryLockError::WouldBlock) => return,
+        }
+    };
+    if !exiting.load(Ordering::SeqCst) {
+        locked.release_all();
+    }
+}
+
+// releases everything when dropped, including while a panic unwinds the input loop
+pub struct ReleaseOnDrop(pub SharedOutputs);
+
+impl Drop for ReleaseOnDrop {
+    fn drop(&mut self) {
+        release_for_exit(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::mpsc;
+
+    use evdev::{EventType, InputEvent, Key};
+
+    use super::*;
+    use crate::output::{describe_frame, KeyTracker, OutputSink, Recorder};
+
+    // a keyboard whose frames stay readable once it is boxed into the outputs
+    #[derive(Clone, Default)]
+    struct Keyboard(Arc<Mutex<Vec<String>>>);
+
+    impl OutputSink for Keyboard {
+        fn send(&mut self, events: &[InputEvent]) {
+            self.0.lock().unwrap().push(describe_frame(events));
+        }
+    }
+
+    impl Keyboard {
+        fn sent(&self) -> Vec<String> {
+            self.0.lock().unwrap().drain(..).collect()
+        }
+    }
+
+    // outputs holding KEY_A down
+    fn holding() -> (SharedOutputs, Keyboard) {
+        let keyboard = Keyboard::default();
+        let mut outputs = Outputs {
+            keyboard: KeyTracker::new(Box::new(keyboard.clone()) as BoxedSink),
+            pointer: Box::new(Recorder::default()),
+            gamepads: HashMap::new(),
+            paused: false,
+        };
+        let press = InputEvent::new(EventType::KEY, Key::KEY_A.code(), 1);
+        outputs.keyboard.send(&[press]);
+        outputs.keyboard.flush();
+        keyboard.sent();
+        (Arc::new(Mutex::new(outputs)), keyboard)
+    }
+
+    #[test]
+    fn exiting_releases_once() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        release_once(&exiting, &outputs);
+        assert!(exiting.load(Ordering::SeqCst));
+        assert_eq!(keyboard.sent(), ["KEY_A up"]);
+        // a second signal, or the drop after it, doesn't touch the outputs
+        let _held = outputs.lock().unwrap();
+        release_once(&exiting, &outputs);
+        assert!(keyboard.sent().is_empty());
+    }
+
+    #[test]
+    fn exiting_releases_outputs_a_panic_poisoned() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        let poisoner = outputs.clone();
+        let _ = thread::spawn(move || {
+            let _held = poisoner.lock().unwrap();
+            panic!("poisoned on purpose");
+        })
+        .join();
+        assert!(outputs.is_poisoned());
+        release_once(&exiting, &outputs);
+        assert_eq!(keyboard.sent(), ["KEY_A up"]);
+    }
+
+    #[test]
+    fn a_panic_releases_and_carries_on() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        release_on_panic(&exiting, &outputs, Duration::ZERO);
+        assert_eq!(keyboard.sent(), ["KEY_A up"]);
+        assert!(!exiting.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn a_panic_waits_for_a_busy_thread() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        let (locked_tx, locked) = mpsc::channel();
+        let busy = outputs.clone();
+        let thread = thread::spawn(move || {
+            let _held = busy.lock().unwrap();
+            locked_tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(20));
+        });
+        locked.recv().unwrap();
+        release_on_panic(&exiting, &outputs, Duration::from_secs(5));
+        assert_eq!(keyboard.sent(), ["KEY_A up"]);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn a_panic_gives_up_on_a_lock_that_is_never_let_go() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        // like a panic in the input loop while it holds the outputs
+        let held = outputs.lock().unwrap();
+        let start = Instant::now();
+        release_on_panic(&exiting, &outputs, Duration::from_millis(10));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        drop(held);
+        assert!(keyboard.sent().is_empty());
+    }
+
+    #[test]
+    fn a_panic_after_exiting_releases_nothing_more() {
+        let exiting = AtomicBool::new(false);
+        let (outputs, keyboard) = holding();
+        release_once(&exiting, &outputs);
+        keyboard.sent();
+        // a key pressed by a thread still running, after the exit released everything
+        let press = InputEvent::new(EventType::KEY, Key::KEY_B.code(), 1);
+        outputs.lock().unwrap().keyboard.send(&[press]);
+        outputs.lock().unwrap().keyboard.flush();
+        keyboard.sent();
+        release_on_panic(&exiting, &outputs, Duration::ZERO);
+        assert!(keyboard.sent().is_empty());
+    }
+}
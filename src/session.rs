@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::frames::{diff, Frame};
+use crate::gamepad::GamepadMirror;
+use crate::input::{read_session, InputUpdate, PadEvent};
+use crate::mapper::{Mapper, Outputs, FRAME_INTERVAL};
+use crate::output::{describe_frame, KeyTracker, Recorder};
+use crate::profile::Profile;
+use crate::types::{BasicControllerState, ValueStore};
+
+// a replay keeps running this long after the last event, so holds and timeouts resolve
+const REPLAY_TAIL: Duration = Duration::from_secs(1);
+
+// differing lines shown per side before the rest is only counted
+const DIFF_LINES: usize = 20;
+
+// writes pad input to a session file as it arrives, one changed input per line
+pub struct SessionRecorder {
+    file: BufWriter<File>,
+    start: Instant,
+    last: Frame,
+}
+
+impl SessionRecorder {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let start = Instant::now();
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            start,
+            last: Frame {
+                time: start,
+                state: BasicControllerState::default(),
+            },
+        })
+    }
+
+    pub fn record(&mut self, update: &InputUpdate) -> io::Result<()> {
+        let next = Frame {
+            time: update.time,
+            state: update.state,
+        };
+        let at = update.time.saturating_duration_since(self.start);
+        for (input, value) in diff(&self.last, &next).changed {
+            writeln!(self.file, "{}", PadEvent { at, input, value })?;
+        }
+        // flushed every time, so a session survives padmixer being killed
+        self.file.flush()?;
+        self.last = next;
+        Ok(())
+    }
+}
+
+// plays a session through a fresh mapper as fast as it goes, on the same frame
+// schedule the live loop keeps, and describes each frame of output as
+// "<ms> <device>: <events>". the same session and profile always give the same lines.
+pub fn replay(profile: Profile, events: &[PadEvent]) -> Vec<String> {
+    let mut events = events.to_vec();
+    events.sort_by_key(|event| event.at);
+    let end = events.last().map_or(Duration::ZERO, |event| event.at) + REPLAY_TAIL;
+
+    let mut outputs = Outputs {
+        keyboard: KeyTracker::new(Recorder::default()),
+        pointer: Recorder::default(),
+        gamepads: HashMap::new(),
+        paused: false,
+    };
+    // a session is one pad, in the first slot
+    if profile.gamepad.is_some() {
+        outputs
+            .gamepads
+            .insert(0, (Recorder::default(), GamepadMirror::default()));
+    }
+    let mut store = ValueStore::new();
+    let mut mapper = Mapper::new(0, profile, &mut store);
+
+    // any instant works as the start, only the offsets from it matter
+    let start = Instant::now();
+    let mut state = BasicControllerState::default();
+    let mut pending = events.iter().peekable();
+    let mut next_tick = FRAME_INTERVAL;
+    let mut lines = Vec::new();
+    loop {
+        // input arriving before the next tick gets a frame of its own, like it would live
+        let at = match pending.peek() {
+            Some(event) if event.at <= next_tick => event.at,
+            _ => next_tick,
+        };
+        if at > end {
+            break;
+        }
+        while let Some(event) = pending.next_if(|event| event.at <= at) {
+            event.apply(&mut state);
+        }
+        let tick = at >= next_tick;
+        if tick {
+            next_tick += FRAME_INTERVAL;
+        }
+        mapper.frame(&state, start + at, tick, &mut outputs, &mut store);
+
+        let mut devices = vec![
+            ("keyboard", outputs.keyboard.sink()),
+            ("pointer", &mut outputs.pointer),
+        ];
+        if let Some((gamepad, _)) = outputs.gamepads.get_mut(&0) {
+            devices.push(("gamepad", gamepad));
+        }
+        for (name, recorder) in devices {
+            for frame in recorder.frames.drain(..) {
+                let described = describe_frame(&frame);
+                if !described.is_empty() {
+                    lines.push(format!("{} {}: {}", at.as_millis(), name, described));
+                }
+            }
+        }
+    }
+    lines
+}
+
+// replays a session and compares its output with a golden file, printing where
+// they differ. a missing golden file is written from this replay instead.
+pub fn check(profile: Profile, session: &Path, golden: &Path) -> io::Result<bool> {
+    let lines = replay(profile, &read_session(session)?);
+    let expected = match fs::read_to_string(golden) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut src = lines.join("\n");
+            src.push('\n');
+            fs::write(golden, src)?;
+            println!("wrote {}", golden.display());
+            return Ok(true);
+        }
+        Err(e) => return Err(e),
+    };
+    let expected: Vec<&str> = expected.lines().collect();
+    let diff = diff_lines(&expected, &lines);
+    if diff.is_empty() {
+        println!("{} matches", golden.display());
+        return Ok(true);
+    }
+    println!("{} differs:", golden.display());
+    for line in diff {
+        println!("{}", line);
+    }
+    Ok(false)
+}
+
+// the lines between the common start and end of both sides, "-" for expected
+// lines that are missing and "+" for new ones. empty when they match.
+pub fn diff_lines(expected: &[&str], actual: &[String]) -> Vec<String> {
+    let same = |(expected, actual): (&&str, &String)| *expected == actual.as_str();
+    let prefix = expected
+        .iter()
+        .zip(actual.iter())
+        .take_while(|pair| same(*pair))
+        .count();
+    let (expected, actual) = (&expected[prefix..], &actual[prefix..]);
+    let suffix = expected
+        .iter()
+        .rev()
+        .zip(actual.iter().rev())
+        .take_while(|pair| same(*pair))
+        .count();
+    let expected = &expected[..expected.len() - suffix];
+    let actual = &actual[..actual.len() - suffix];
+    if expected.is_empty() && actual.is_empty() {
+        return Vec::new();
+    }
+
+    let mut diff = vec![format!("@@ line {}", prefix + 1)];
+    let mut side = |sign: char, lines: Vec<&str>| {
+        for line in lines.iter().take(DIFF_LINES) {
+            diff.push(format!("{}{}", sign, line));
+        }
+        if lines.len() > DIFF_LINES {
+            diff.push(format!("{}... {} more", sign, lines.len() - DIFF_LINES));
+        }
+    };
+    side('-', expected.to_vec());
+    side('+', actual.iter().map(String::as_str).collect());
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // the sample session, its profile and its expected output
+    fn sample(name: &str) -> (Profile, PathBuf, PathBuf) {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("sessions");
+        let src = fs::read_to_string(dir.join(format!("{}.toml", name))).unwrap();
+        (
+            Profile::parse(&src).unwrap(),
+            dir.join(format!("{}.txt", name)),
+            dir.join(format!("{}.golden", name)),
+        )
+    }
+
+    #[test]
+    fn sample_session_matches_its_golden_file() {
+        let (profile, session, golden) = sample("submenu");
+        assert!(golden.exists(), "{} is missing", golden.display());
+        assert!(check(profile, &session, &golden).unwrap());
+    }
+
+    #[test]
+    fn replays_are_deterministic() {
+        let (profile, session, _) = sample("submenu");
+        let events = read_session(&session).unwrap();
+        let first = replay(profile, &events);
+        assert!(!first.is_empty());
+        let (profile, _, _) = sample("submenu");
+        assert_eq!(first, replay(profile, &events));
+    }
+
+    #[test]
+    fn diff_shows_only_the_lines_in_between() {
+        let actual =
+            |lines: &[&str]| -> Vec<String> { lines.iter().map(|l| l.to_string()).collect() };
+        assert!(diff_lines(&["a", "b"], &actual(&["a", "b"])).is_empty());
+        assert_eq!(
+            diff_lines(&["a", "b", "c"], &actual(&["a", "x", "y", "c"])),
+            ["@@ line 2", "-b", "+x", "+y"]
+        );
+        assert_eq!(diff_lines(&["a"], &actual(&[])), ["@@ line 1", "-a"]);
+    }
+}
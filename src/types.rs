@@ -7,6 +7,7 @@ use std::{
 };
 
 use gilrs::{Axis, Button};
+use serde::Deserialize;
 
 pub trait ListItemData: 'static + Send + Sync + ToAny + std::fmt::Display {}
 
@@ -117,7 +118,7 @@ where
     }
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
 pub enum CommonAnalog {
     LeftStickX,
     LeftStickY,
@@ -130,11 +131,15 @@ pub enum CommonAnalog {
     Unknown,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Deserialize)]
 pub enum CommonButton {
     LeftStickPress,
     RightStickPress,
+    // nothing reads these, the shoulder buttons come in as LegacyLT and LegacyRT.
+    // profiles can't name them, so no bind waits on them forever.
+    #[serde(skip_deserializing)]
     LeftShoulder,
+    #[serde(skip_deserializing)]
     RightShoulder,
     FaceSouth,
     FaceEast,